@@ -0,0 +1,60 @@
+//! A simple computer player, for demos and for soak testing without
+//! anyone at the keyboard. It only looks at what's on the field, so it
+//! plays the same way every time for the same seed.
+
+use crate::entities::Entity;
+use crate::game::game_loop::{CtrlEvent, GameLoop};
+use crate::utils::Dir;
+
+/// How many rows above the top of the player a falling missile counts as a threat.
+const LOOKAHEAD: usize = 4;
+
+/// Decides what to do this frame: get out from under any missile that's
+/// about to land, otherwise line up with the nearest invader and fire.
+pub fn commands(game: &GameLoop) -> Vec<CtrlEvent> {
+    let player = game.player().position;
+    let top = game.player().top();
+    let width = game.screen().size().0;
+    let (left, right) = game.player().sprite().reach();
+
+    // Whether a missile's about to land anywhere on the player, were it at x
+    let danger = |x: usize| {
+        game.missiles().iter().any(|missile| {
+            missile.direction == Dir::Down
+                && missile.position.0 + left >= x
+                && missile.position.0 <= x + right
+                && missile.position.1 <= player.1
+                && top.saturating_sub(missile.position.1) <= LOOKAHEAD
+        })
+    };
+    let can_go_left = player.0 > left && !danger(player.0 - 1);
+    let can_go_right = player.0 + right + 1 < width && !danger(player.0 + 1);
+
+    // Head for the nearest spot out of the way, even if it's a few steps off
+    if danger(player.0) {
+        for distance in 1..width {
+            if player.0 >= left + distance && !danger(player.0 - distance) {
+                return vec![CtrlEvent::Left];
+            }
+            if player.0 + right + distance < width && !danger(player.0 + distance) {
+                return vec![CtrlEvent::Right];
+            }
+        }
+
+        return Vec::new();
+    }
+
+    // Nearest column first, then the lowest, as that one's closest to landing
+    let target = game
+        .invaders()
+        .iter()
+        .map(|invader| invader.position)
+        .min_by_key(|position| ((position.0 as isize - player.0 as isize).abs(), std::cmp::Reverse(position.1)));
+
+    match target {
+        Some(target) if target.0 < player.0 && can_go_left => vec![CtrlEvent::Left],
+        Some(target) if target.0 > player.0 && can_go_right => vec![CtrlEvent::Right],
+        Some(target) if target.0 == player.0 => vec![CtrlEvent::Shoot],
+        _ => Vec::new(),
+    }
+}
@@ -0,0 +1,230 @@
+use failure::Error;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How many times a second the game logic runs. All the speeds and delays
+/// in the game are given in seconds, and turned into ticks with this.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TickRate(u32);
+
+/// When the game ticks and draws, set up front so a game plays the same
+/// however long each frame takes to draw.
+#[derive(Clone, Copy)]
+pub struct Timing {
+    pub tick_rate: TickRate,
+    /// Frames drawn a second. Never more than one per tick.
+    pub render_rate: u32,
+    /// Most ticks run back to back to catch up after a slow frame. Any
+    /// further behind than that, and the game slows down instead.
+    pub max_catch_up: u32,
+    /// Run as fast as possible, without waiting for the time to pass.
+    pub simulated: bool,
+}
+
+/// A fixed-timestep clock. Real time is collected into an accumulator and
+/// paid out one tick at a time, so the game advances by the same amount
+/// each tick, whether the terminal keeps up or not.
+pub struct Clock {
+    timing: Timing,
+    last: Instant,
+    accumulator: Duration,
+    /// Game time, advanced one tick at a time.
+    time: Duration,
+    next_render: Duration,
+}
+
+impl TickRate {
+    /// Things move at most one cell a tick, so any slower and the fastest
+    /// of them, the missiles at 33 cells a second, would fall behind.
+    pub const MIN: u32 = 33;
+    pub const MAX: u32 = 1000;
+
+    pub fn new(per_second: u32) -> Result<Self, Error> {
+        if !(Self::MIN..=Self::MAX).contains(&per_second) {
+            return Err(format_err!(
+                "Tick rate must be between {} and {}, not {}",
+                Self::MIN,
+                Self::MAX,
+                per_second
+            ));
+        }
+
+        Ok(TickRate(per_second))
+    }
+
+    pub fn per_second(self) -> u32 {
+        self.0
+    }
+
+    /// How long each tick lasts.
+    pub fn period(self) -> Duration {
+        Duration::from_secs(1) / self.0
+    }
+
+    /// The nearest number of ticks to the given time, and at least one.
+    pub fn ticks(self, seconds: f32) -> u32 {
+        std::cmp::max((seconds * self.0 as f32).round() as u32, 1)
+    }
+
+    /// Ticks between each move of something going `speed` cells a second.
+    pub fn interval(self, speed: f32) -> u32 {
+        self.ticks(1.0 / speed)
+    }
+}
+
+impl Default for TickRate {
+    /// Roughly the 30ms frames the game has always run at.
+    fn default() -> Self {
+        TickRate(33)
+    }
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Self {
+            tick_rate: TickRate::default(),
+            render_rate: 30,
+            max_catch_up: 5,
+            simulated: false,
+        }
+    }
+}
+
+impl Clock {
+    pub fn new(timing: Timing) -> Self {
+        Self {
+            timing,
+            last: Instant::now(),
+            accumulator: Duration::from_secs(0),
+            time: Duration::from_secs(0),
+            next_render: Duration::from_secs(0),
+        }
+    }
+
+    /// Waits until at least one tick is due, and returns how many to run.
+    pub fn advance(&mut self) -> u32 {
+        let period = self.timing.tick_rate.period();
+
+        if self.timing.simulated {
+            self.time += period;
+            return 1;
+        }
+
+        loop {
+            let now = Instant::now();
+            self.accumulator += now.duration_since(self.last);
+            self.last = now;
+
+            if self.accumulator >= period {
+                break;
+            }
+
+            thread::sleep(period - self.accumulator);
+        }
+
+        let due = (self.accumulator.as_nanos() / period.as_nanos()) as u32;
+        let ticks = std::cmp::min(due, std::cmp::max(self.timing.max_catch_up, 1));
+
+        if ticks < due {
+            debug!("Dropping {} ticks to catch up", due - ticks);
+            self.accumulator = Duration::from_secs(0);
+        } else {
+            self.accumulator -= period * ticks;
+        }
+
+        self.time += period * ticks;
+        ticks
+    }
+
+    /// Whether it's time to draw another frame. Asking counts as drawing.
+    pub fn should_render(&mut self) -> bool {
+        let period = Duration::from_secs(1) / std::cmp::max(self.timing.render_rate, 1);
+
+        if self.time < self.next_render {
+            return false;
+        }
+
+        // Keep to the schedule, unless we've fallen a whole frame behind
+        self.next_render = std::cmp::max(self.next_render + period, self.time);
+        true
+    }
+
+    /// Game time, for timestamping frames.
+    pub fn time(&self) -> Duration {
+        self.time
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timing(tick_rate: u32, render_rate: u32) -> Timing {
+        Timing {
+            tick_rate: TickRate::new(tick_rate).unwrap(),
+            render_rate,
+            max_catch_up: 5,
+            simulated: false,
+        }
+    }
+
+    /// A clock that last ticked `ticks` and a bit periods ago.
+    fn behind(timing: Timing, ticks: u32) -> Clock {
+        let mut clock = Clock::new(timing);
+        let period = timing.tick_rate.period();
+        clock.last = Instant::now() - period * ticks - period / 2;
+        clock
+    }
+
+    #[test]
+    fn catches_up_on_missed_ticks() {
+        let mut clock = behind(timing(40, 30), 3);
+
+        assert_eq!(clock.advance(), 3);
+        assert_eq!(clock.time(), Duration::from_millis(75));
+    }
+
+    #[test]
+    fn drops_ticks_too_far_behind() {
+        let mut clock = behind(timing(40, 30), 50);
+
+        assert_eq!(clock.advance(), 5);
+        assert_eq!(clock.time(), Duration::from_millis(125));
+        // The rest are gone, rather than paid out over the next few calls
+        assert!(clock.accumulator < timing(40, 30).tick_rate.period());
+    }
+
+    #[test]
+    fn simulated_clocks_tick_once_a_call() {
+        let mut clock = Clock::new(Timing { simulated: true, ..timing(100, 30) });
+
+        for _ in 0..10 {
+            assert_eq!(clock.advance(), 1);
+        }
+        assert_eq!(clock.time(), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn renders_less_often_than_it_ticks() {
+        let mut clock = Clock::new(Timing { simulated: true, ..timing(100, 25) });
+        let mut rendered = Vec::new();
+
+        for _ in 0..100 {
+            clock.advance();
+            if clock.should_render() {
+                rendered.push(clock.time().as_millis());
+            }
+        }
+
+        // Straight away, then every 40ms of game time on the dot
+        let expected = std::iter::once(10).chain((1..=25).map(|i| i * 40)).collect::<Vec<_>>();
+        assert_eq!(rendered, expected);
+    }
+
+    #[test]
+    fn refuses_rates_out_of_range() {
+        assert!(TickRate::new(TickRate::MIN - 1).is_err());
+        assert!(TickRate::new(TickRate::MIN).is_ok());
+        assert!(TickRate::new(TickRate::MAX + 1).is_err());
+    }
+}
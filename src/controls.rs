@@ -0,0 +1,380 @@
+use crate::game::game_loop::CtrlEvent as GameEvent;
+use crate::game::menu_loop::CtrlEvent as MenuEvent;
+use crate::input::Key;
+use failure::Error;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Game actions that must always have a key.
+const NEEDED_GAME: &[GameEvent] = &[GameEvent::Pause, GameEvent::Quit];
+/// Menu actions that must always have a key.
+const NEEDED_MENU: &[MenuEvent] = &[MenuEvent::Select, MenuEvent::Quit];
+
+/// A starting point for the bindings, before any changes from the file.
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    Arrows,
+    Wasd,
+    Vi,
+}
+
+/// Which keys do what, in the game and in the menus.
+///
+/// Loaded from `controls.toml` under the XDG config directory, which picks
+/// a preset and then rebinds whichever actions it likes, e.g.
+///
+/// ```toml
+/// preset = "wasd"
+///
+/// [game]
+/// shoot = ["space", "k"]
+///
+/// [menu]
+/// select = ["enter"]
+/// ```
+///
+/// Ctrl-C always quits, whatever the bindings say, so there's a way out.
+/// Pausing, quitting and selecting always keep at least one key too.
+#[derive(Clone)]
+pub struct Controls {
+    /// In the order they're listed on the controls screen.
+    game: Vec<(GameEvent, Vec<Key>)>,
+    menu: Vec<(MenuEvent, Vec<Key>)>,
+}
+
+/// The file as written, with keys and actions by name.
+#[derive(Default, Deserialize, Serialize)]
+struct ControlsFile {
+    preset: Option<Preset>,
+    #[serde(default)]
+    game: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    menu: BTreeMap<String, Vec<String>>,
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Preset::Arrows => write!(f, "Arrows"),
+            Preset::Wasd => write!(f, "WASD"),
+            Preset::Vi => write!(f, "vi"),
+        }
+    }
+}
+
+impl Preset {
+    pub fn next(self) -> Self {
+        match self {
+            Preset::Arrows => Preset::Wasd,
+            Preset::Wasd => Preset::Vi,
+            Preset::Vi => Preset::Arrows,
+        }
+    }
+}
+
+impl fmt::Display for MenuEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MenuEvent::Up => write!(f, "up"),
+            MenuEvent::Down => write!(f, "down"),
+            MenuEvent::Select => write!(f, "select"),
+            MenuEvent::Quit => write!(f, "quit"),
+        }
+    }
+}
+
+impl FromStr for MenuEvent {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "up" => Ok(MenuEvent::Up),
+            "down" => Ok(MenuEvent::Down),
+            "select" => Ok(MenuEvent::Select),
+            "quit" => Ok(MenuEvent::Quit),
+            s => Err(format_err!("Unknown menu action: {:?}", s)),
+        }
+    }
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        Self::preset(Preset::Arrows)
+    }
+}
+
+impl Controls {
+    pub fn preset(preset: Preset) -> Self {
+        use crate::input::Key::*;
+
+        let (left, right, shoot, up, down) = match preset {
+            Preset::Arrows => (vec![Left], vec![Right], vec![Char(' ')], vec![Up, Left], vec![Down, Right]),
+            Preset::Wasd => (
+                vec![Char('a')],
+                vec![Char('d')],
+                vec![Char(' '), Char('w')],
+                vec![Char('w'), Up],
+                vec![Char('s'), Down],
+            ),
+            Preset::Vi => (
+                vec![Char('h')],
+                vec![Char('l')],
+                vec![Char(' '), Char('k')],
+                vec![Char('k'), Up],
+                vec![Char('j'), Down],
+            ),
+        };
+
+        Self {
+            game: vec![
+                (GameEvent::Left, left),
+                (GameEvent::Right, right),
+                (GameEvent::Shoot, shoot),
+                (GameEvent::Pause, vec![Char('p'), Esc]),
+                (GameEvent::Quit, vec![Char('q')]),
+            ],
+            menu: vec![
+                (MenuEvent::Up, up),
+                (MenuEvent::Down, down),
+                (MenuEvent::Select, vec![Char(' '), Enter]),
+                (MenuEvent::Quit, vec![Char('q')]),
+            ],
+        }
+    }
+
+    /// What a key does in the game, if anything.
+    pub fn game(&self, key: Key) -> Option<GameEvent> {
+        if key == Key::Ctrl('c') {
+            return Some(GameEvent::Quit);
+        }
+
+        Self::find(&self.game, key)
+    }
+
+    /// What a key does in the menus, if anything.
+    pub fn menu(&self, key: Key) -> Option<MenuEvent> {
+        if key == Key::Ctrl('c') {
+            return Some(MenuEvent::Quit);
+        }
+
+        Self::find(&self.menu, key)
+    }
+
+    pub fn game_bindings(&self) -> &[(GameEvent, Vec<Key>)] {
+        &self.game
+    }
+
+    pub fn menu_bindings(&self) -> &[(MenuEvent, Vec<Key>)] {
+        &self.menu
+    }
+
+    /// Makes the key the only one for the action, taking it off any other.
+    /// An action left with no keys gets the ones this action had instead.
+    /// Fails, changing nothing, if pausing or quitting would have no key.
+    pub fn bind_game(&mut self, event: GameEvent, key: Key) -> Result<(), Error> {
+        let mut rv = self.clone();
+        Self::bind(&mut rv.game, event, key);
+        rv.check()?;
+        *self = rv;
+        Ok(())
+    }
+
+    /// Like `bind_game`, but fails if selecting or quitting would have no key.
+    pub fn bind_menu(&mut self, event: MenuEvent, key: Key) -> Result<(), Error> {
+        let mut rv = self.clone();
+        Self::bind(&mut rv.menu, event, key);
+        rv.check()?;
+        *self = rv;
+        Ok(())
+    }
+
+    fn find<E: Copy>(bindings: &[(E, Vec<Key>)], key: Key) -> Option<E> {
+        bindings
+            .iter()
+            .find(|(_, keys)| keys.contains(&key))
+            .map(|(event, _)| *event)
+    }
+
+    fn bind<E: PartialEq>(bindings: &mut [(E, Vec<Key>)], event: E, key: Key) {
+        let spare = bindings
+            .iter()
+            .find(|(bound, _)| *bound == event)
+            .map_or(Vec::new(), |(_, keys)| keys.iter().copied().filter(|k| *k != key).collect());
+
+        for (bound, keys) in bindings.iter_mut() {
+            if *bound == event {
+                *keys = vec![key];
+            } else if keys.contains(&key) {
+                keys.retain(|k| *k != key);
+
+                // Swap them over, rather than leave this one with nothing
+                if keys.is_empty() {
+                    *keys = spare.clone();
+                }
+            }
+        }
+    }
+
+    /// Makes sure there's always a key to pause, select and quit with.
+    fn check(&self) -> Result<(), Error> {
+        let unbound = |keys: &Vec<Key>| keys.is_empty();
+        let game = self.game.iter().find(|(event, keys)| NEEDED_GAME.contains(event) && unbound(keys));
+        let menu = self.menu.iter().find(|(event, keys)| NEEDED_MENU.contains(event) && unbound(keys));
+
+        match (game, menu) {
+            (Some((event, _)), _) => Err(format_err!("{} needs a key in the game", event)),
+            (_, Some((event, _))) => Err(format_err!("{} needs a key in the menus", event)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Where the bindings are stored, under the XDG config directory.
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("space-invaders").join("controls.toml"))
+    }
+
+    /// Loads the bindings, falling back to the arrow keys if the file is
+    /// missing or can't be read.
+    pub fn load_or_default() -> Self {
+        match Self::path() {
+            Some(path) if path.exists() => match Self::load(&path) {
+                Ok(controls) => controls,
+                Err(e) => {
+                    warn!("Ignoring controls file {}: {}", path.display(), e);
+                    Self::default()
+                }
+            },
+            _ => Self::default(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(source: &str) -> Result<Self, Error> {
+        let file: ControlsFile = toml::from_str(source)?;
+        let mut controls = Self::preset(file.preset.unwrap_or(Preset::Arrows));
+
+        for (action, keys) in &file.game {
+            let event: GameEvent = action.parse()?;
+            let keys = Self::parse_keys(keys)?;
+
+            if let Some((_, bound)) = controls.game.iter_mut().find(|(bound, _)| *bound == event) {
+                *bound = keys;
+            }
+        }
+
+        for (action, keys) in &file.menu {
+            let event: MenuEvent = action.parse()?;
+            let keys = Self::parse_keys(keys)?;
+
+            if let Some((_, bound)) = controls.menu.iter_mut().find(|(bound, _)| *bound == event) {
+                *bound = keys;
+            }
+        }
+
+        controls.check()?;
+        Ok(controls)
+    }
+
+    fn parse_keys(keys: &[String]) -> Result<Vec<Key>, Error> {
+        keys.iter().map(|key| key.parse()).collect()
+    }
+
+    /// Writes out every binding, so the file doesn't depend on the presets.
+    pub fn save(&self) -> Result<(), Error> {
+        let path = Self::path().ok_or_else(|| format_err!("No config directory available"))?;
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let names = |keys: &[Key]| keys.iter().map(|key| key.to_string()).collect();
+        let file = ControlsFile {
+            preset: None,
+            game: self
+                .game
+                .iter()
+                .map(|(event, keys)| (event.to_string(), names(keys)))
+                .collect(),
+            menu: self
+                .menu
+                .iter()
+                .map(|(event, keys)| (event.to_string(), names(keys)))
+                .collect(),
+        };
+
+        fs::write(&path, toml::to_string(&file)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::Key::*;
+
+    #[test]
+    fn parses_a_preset_with_changes() {
+        let controls = Controls::parse("preset = \"wasd\"\n[game]\nshoot = [\"k\"]\n[menu]\nselect = [\"enter\"]\n").unwrap();
+
+        assert_eq!(controls.game(Char('a')), Some(GameEvent::Left));
+        assert_eq!(controls.game(Char('k')), Some(GameEvent::Shoot));
+        assert_eq!(controls.game(Char(' ')), None);
+        assert_eq!(controls.menu(Enter), Some(MenuEvent::Select));
+        assert_eq!(controls.menu(Char(' ')), None);
+        assert_eq!(controls.menu(Ctrl('c')), Some(MenuEvent::Quit));
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        let error = |source| Controls::parse(source).err().unwrap().to_string();
+
+        assert!(Controls::parse("").is_ok());
+        assert!(Controls::parse("[game]\njump = [\"w\"]\n").is_err());
+        assert!(Controls::parse("[game]\nshoot = [\"ctrl-\"]\n").is_err());
+        assert!(Controls::parse("preset = \"emacs\"\n").is_err());
+        assert_eq!(error("[menu]\nselect = []\n"), "select needs a key in the menus");
+        assert_eq!(error("[game]\npause = []\n"), "pause needs a key in the game");
+    }
+
+    #[test]
+    fn takes_a_rebound_key_off_other_actions() {
+        let mut controls = Controls::default();
+        controls.bind_game(GameEvent::Shoot, Left).unwrap();
+
+        assert_eq!(controls.game(Left), Some(GameEvent::Shoot));
+        // Move left had nothing else, so it gets shoot's old key
+        assert_eq!(controls.game(Char(' ')), Some(GameEvent::Left));
+        assert_eq!(controls.game_bindings()[0], (GameEvent::Left, vec![Char(' ')]));
+        assert_eq!(controls.game_bindings()[2], (GameEvent::Shoot, vec![Left]));
+    }
+
+    #[test]
+    fn never_leaves_select_without_a_key() {
+        let mut controls = Controls::default();
+        controls.bind_menu(MenuEvent::Up, Enter).unwrap();
+        controls.bind_menu(MenuEvent::Down, Char(' ')).unwrap();
+
+        let select = controls.menu_bindings().iter().find(|(event, _)| *event == MenuEvent::Select).unwrap();
+        assert!(!select.1.is_empty());
+        for key in &select.1 {
+            assert_eq!(controls.menu(*key), Some(MenuEvent::Select));
+        }
+    }
+
+    #[test]
+    fn refuses_to_unbind_quit() {
+        let mut controls = Controls::parse("[game]\nshoot = []\n").unwrap();
+
+        assert!(controls.bind_game(GameEvent::Shoot, Char('q')).is_err());
+        assert_eq!(controls.game(Char('q')), Some(GameEvent::Quit));
+        assert_eq!(controls.game(Char(' ')), None);
+    }
+}
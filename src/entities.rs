@@ -1,33 +1,185 @@
 use crate::utils::Coord;
 use crate::utils::Dir;
+use serde::{Deserialize, Serialize};
+
+/// How something looks: one or more frames of animation, each one or more
+/// rows of text, all the same size. Spaces are see-through, and can't be hit.
+pub struct Sprite {
+    pub frames: &'static [&'static [&'static str]],
+    /// The cell an entity's position refers to, from the top left corner.
+    pub origin: Coord,
+}
 
 pub trait Entity {
     fn position(&self) -> &Coord;
-    fn icon(&self) -> char;
+    fn sprite(&self) -> &'static Sprite;
+
+    /// Which of the sprite's frames to show.
+    fn frame(&self) -> usize {
+        0
+    }
+
+    /// The cells it's drawn in, and what's drawn in each. These are what
+    /// it collides with.
+    fn cells(&self) -> Vec<(Coord, char)> {
+        self.sprite().cells(self.frame(), self.position())
+    }
+
+    fn footprint(&self) -> Vec<Coord> {
+        self.cells().into_iter().map(|(position, _)| position).collect()
+    }
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Player {
     pub position: Coord,
-    pub missile_timer: u8,
+    pub missile_timer: u32,
+    pub lives: u8,
+    pub invulnerable: u32,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InvaderKind {
+    Squid,
+    Crab,
+    Octopus,
+    /// Takes several hits to bring down.
+    Tank,
+    /// Breaks formation now and then to swoop at the player.
+    Diver,
+    /// Splits into two octopuses when destroyed.
+    Splitter,
+    /// Fires bursts of its own, on top of the formation's schedule.
+    Shooter,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Invader {
+    pub kind: InvaderKind,
+    pub position: Coord,
+    pub health: u8,
+    /// Divers out of formation, heading for the player.
+    pub diving: bool,
+    /// How many steps it's taken, for the animation.
+    #[serde(default)]
+    pub steps: u32,
+}
+
+/// The mystery ship. Crosses the top row now and then, and is worth
+/// a random bonus if shot down.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Ufo {
+    /// The leftmost cell of the sprite
+    pub position: Coord,
     pub direction: Dir,
+    pub points: u32,
+}
+
+/// One cell of a bunker. Bunkers are just clumps of these.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Bunker {
     pub position: Coord,
+    pub health: u8,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Missile {
     pub position: Coord,
     pub direction: Dir,
 }
 
+impl Sprite {
+    /// A sprite positioned by its top left corner.
+    pub const fn new(frames: &'static [&'static [&'static str]]) -> Self {
+        Self {
+            frames,
+            origin: Coord(0, 0),
+        }
+    }
+
+    /// Width and height, in cells.
+    pub fn size(&self) -> Coord {
+        let rows = self.frames[0];
+        let width = rows.iter().map(|row| row.chars().count()).max().unwrap_or(0);
+        Coord(width, rows.len())
+    }
+
+    /// The rows of the given frame. Animations loop.
+    pub fn frame(&self, frame: usize) -> &'static [&'static str] {
+        self.frames[frame % self.frames.len()]
+    }
+
+    /// The cells the frame covers with its origin at `position`, skipping
+    /// any that would be off the top or left of the map.
+    pub fn cells(&self, frame: usize, position: &Coord) -> Vec<(Coord, char)> {
+        let mut rv = Vec::new();
+
+        for (y, row) in self.frame(frame).iter().enumerate() {
+            for (x, glyph) in row.chars().enumerate().filter(|(_, glyph)| *glyph != ' ') {
+                let x = (position.0 + x).checked_sub(self.origin.0);
+                let y = (position.1 + y).checked_sub(self.origin.1);
+
+                if let (Some(x), Some(y)) = (x, y) {
+                    rv.push((Coord(x, y), glyph));
+                }
+            }
+        }
+
+        rv
+    }
+
+    /// How many cells it reaches to the left of its position, and to the right.
+    pub fn reach(&self) -> (usize, usize) {
+        (self.origin.0, self.size().0 - self.origin.0 - 1)
+    }
+}
+
 impl Player {
+    pub const LIVES: u8 = 3;
+    /// How many seconds the player is untouchable for after respawning.
+    pub const INVULNERABLE_TIME: f32 = 2.0;
+    /// The cannon. Its position is the middle of the bottom row, so levels
+    /// place the player by where it stands.
+    pub const SPRITE: Sprite = Sprite {
+        frames: &[&[" ^ ", "/_\\"]],
+        origin: Coord(1, 1),
+    };
+
     pub fn new(position: Coord) -> Self {
         Self {
             position,
             missile_timer: 0,
+            lives: Self::LIVES,
+            invulnerable: 0,
         }
     }
+
+    pub fn is_alive(&self) -> bool {
+        self.lives > 0
+    }
+
+    pub fn is_invulnerable(&self) -> bool {
+        self.invulnerable > 0
+    }
+
+    /// Takes a life. Returns whether the player has any left.
+    pub fn kill(&mut self) -> bool {
+        self.lives = self.lives.saturating_sub(1);
+        self.is_alive()
+    }
+
+    /// Puts the player back, untouchable for the given number of ticks.
+    pub fn respawn(&mut self, position: Coord, invulnerable: u32) {
+        self.position = position;
+        self.missile_timer = 0;
+        self.invulnerable = invulnerable;
+    }
+
+    /// The row the top of the player is on.
+    pub fn top(&self) -> usize {
+        self.position.1.saturating_sub(Self::SPRITE.origin.1)
+    }
 }
 
 impl Entity for Player {
@@ -35,16 +187,63 @@ impl Entity for Player {
         &self.position
     }
 
-    fn icon(&self) -> char {
-        '^'
+    fn sprite(&self) -> &'static Sprite {
+        &Self::SPRITE
+    }
+}
+
+impl InvaderKind {
+    /// Base points for shooting one down, before any multipliers.
+    pub fn points(self) -> u32 {
+        match self {
+            InvaderKind::Squid => 30,
+            InvaderKind::Crab => 20,
+            InvaderKind::Octopus => 10,
+            InvaderKind::Tank => 40,
+            InvaderKind::Diver => 50,
+            InvaderKind::Splitter => 30,
+            InvaderKind::Shooter => 40,
+        }
+    }
+
+    pub fn health(self) -> u8 {
+        match self {
+            InvaderKind::Tank => 3,
+            _ => 1,
+        }
     }
 }
 
 impl Invader {
-    pub fn new(position: Coord, direction: Dir) -> Self {
+    pub fn new(kind: InvaderKind, position: Coord) -> Self {
         Invader {
+            kind,
             position,
-            direction,
+            health: kind.health(),
+            diving: false,
+            steps: 0,
+        }
+    }
+
+    /// Takes a hit. Returns whether the invader survived it.
+    pub fn hit(&mut self) -> bool {
+        self.health = self.health.saturating_sub(1);
+        self.health > 0
+    }
+
+    /// What's left behind when this invader is destroyed.
+    pub fn split(&self) -> Vec<Invader> {
+        match self.kind {
+            InvaderKind::Splitter => {
+                let Coord(x, y) = self.position;
+                // Keep in step with the rest of the swarm
+                let octopus = |x| Invader {
+                    steps: self.steps,
+                    ..Invader::new(InvaderKind::Octopus, Coord(x, y))
+                };
+                vec![octopus(x.saturating_sub(1)), octopus(x + 1)]
+            }
+            _ => Vec::new(),
         }
     }
 }
@@ -54,8 +253,103 @@ impl Entity for Invader {
         &self.position
     }
 
-    fn icon(&self) -> char {
-        '@'
+    /// Invaders take up one cell each, so formations can be drawn a
+    /// character at a time. Each has two poses, apart from tanks, which
+    /// show how much damage they've taken instead.
+    fn sprite(&self) -> &'static Sprite {
+        const SQUID: Sprite = Sprite::new(&[&["W"], &["M"]]);
+        const CRAB: Sprite = Sprite::new(&[&["X"], &["x"]]);
+        const OCTOPUS: Sprite = Sprite::new(&[&["@"], &["O"]]);
+        const TANK: Sprite = Sprite::new(&[&["H"], &["h"]]);
+        const DIVER: Sprite = Sprite::new(&[&["V"], &["v"]]);
+        const SPLITTER: Sprite = Sprite::new(&[&["%"], &["&"]]);
+        const SHOOTER: Sprite = Sprite::new(&[&["Y"], &["y"]]);
+
+        match self.kind {
+            InvaderKind::Squid => &SQUID,
+            InvaderKind::Crab => &CRAB,
+            InvaderKind::Octopus => &OCTOPUS,
+            InvaderKind::Tank => &TANK,
+            InvaderKind::Diver => &DIVER,
+            InvaderKind::Splitter => &SPLITTER,
+            InvaderKind::Shooter => &SHOOTER,
+        }
+    }
+
+    fn frame(&self) -> usize {
+        match self.kind {
+            InvaderKind::Tank if self.health > 1 => 0,
+            InvaderKind::Tank => 1,
+            _ => self.steps as usize,
+        }
+    }
+}
+
+impl Ufo {
+    /// Its lights chase round as it goes.
+    pub const SPRITE: Sprite = Sprite::new(&[&["<=O=>"], &["<-O->"]]);
+    /// Cells a second. Much faster than the invaders.
+    pub const SPEED: f32 = 16.0;
+
+    pub fn new(position: Coord, direction: Dir, points: u32) -> Self {
+        Self {
+            position,
+            direction,
+            points,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        Self::SPRITE.size().0
+    }
+}
+
+impl Entity for Ufo {
+    fn position(&self) -> &Coord {
+        &self.position
+    }
+
+    fn sprite(&self) -> &'static Sprite {
+        &Self::SPRITE
+    }
+
+    fn frame(&self) -> usize {
+        self.position.0
+    }
+}
+
+impl Bunker {
+    pub const MAX_HEALTH: u8 = 3;
+
+    pub fn new(position: Coord) -> Self {
+        Self {
+            position,
+            health: Self::MAX_HEALTH,
+        }
+    }
+
+    pub fn hit(&mut self) {
+        self.health = self.health.saturating_sub(1);
+    }
+
+    pub fn is_destroyed(&self) -> bool {
+        self.health == 0
+    }
+}
+
+impl Entity for Bunker {
+    fn position(&self) -> &Coord {
+        &self.position
+    }
+
+    /// Wears away as it's hit, a frame at a time.
+    fn sprite(&self) -> &'static Sprite {
+        const BUNKER: Sprite = Sprite::new(&[&["#"], &["="], &["-"]]);
+        &BUNKER
+    }
+
+    fn frame(&self) -> usize {
+        (Self::MAX_HEALTH - self.health.min(Self::MAX_HEALTH)) as usize
     }
 }
 
@@ -73,11 +367,20 @@ impl Entity for Missile {
         &self.position
     }
 
-    fn icon(&self) -> char {
+    /// The invaders' zig-zag as they fall.
+    fn sprite(&self) -> &'static Sprite {
+        const UP: Sprite = Sprite::new(&[&["!"]]);
+        const DOWN: Sprite = Sprite::new(&[&["\\"], &["/"]]);
+        const SIDEWAYS: Sprite = Sprite::new(&[&["="]]);
+
         match self.direction {
-            Dir::Up => '!',
-            Dir::Down => ';',
-            _ => '=',
+            Dir::Up => &UP,
+            Dir::Down => &DOWN,
+            _ => &SIDEWAYS,
         }
     }
+
+    fn frame(&self) -> usize {
+        self.position.1
+    }
 }
@@ -0,0 +1,216 @@
+use crate::controls::{Controls, Preset};
+use crate::game::game_loop::CtrlEvent as GameEvent;
+use crate::game::menu_loop::CtrlEvent as MenuEvent;
+use crate::game::GameAction;
+use crate::game::Loop;
+use crate::input::{Input, Key};
+use crate::render::Renderer;
+use crate::utils::Screen;
+use failure::Error;
+
+#[derive(PartialEq, Clone, Copy)]
+enum ControlsItem {
+    Game(GameEvent),
+    Menu(MenuEvent),
+    Preset,
+    Back,
+}
+
+impl ControlsItem {
+    fn label(&self) -> &'static str {
+        match self {
+            ControlsItem::Game(GameEvent::Left) => "Move left",
+            ControlsItem::Game(GameEvent::Right) => "Move right",
+            ControlsItem::Game(GameEvent::Shoot) => "Shoot",
+            ControlsItem::Game(GameEvent::Pause) => "Pause",
+            ControlsItem::Game(GameEvent::Quit) => "Quit game",
+            ControlsItem::Menu(MenuEvent::Up) => "Menu up",
+            ControlsItem::Menu(MenuEvent::Down) => "Menu down",
+            ControlsItem::Menu(MenuEvent::Select) => "Menu select",
+            ControlsItem::Menu(MenuEvent::Quit) => "Menu quit",
+            ControlsItem::Preset => "Preset",
+            ControlsItem::Back => "Back",
+        }
+    }
+}
+
+/// Lists the key bindings, and rebinds whichever one is selected to the
+/// next key pressed. Changes are saved on the way out.
+pub struct ControlsLoop {
+    screen: Screen,
+    controls: Controls,
+    items: Vec<ControlsItem>,
+    selected: usize,
+    /// The last preset picked here, if any. Nothing says which one the
+    /// saved bindings started from.
+    preset: Option<Preset>,
+    /// Waiting for a key to bind to the selected item.
+    rebinding: bool,
+    /// Only save if something was actually changed.
+    changed: bool,
+    /// Opened from the pause menu, rather than the main one.
+    in_game: bool,
+    /// Why the last rebind was refused, until the next key press.
+    message: Option<String>,
+}
+
+impl Loop<'_> for ControlsLoop {
+    fn init(screen: Screen) -> Self {
+        Self::new(screen, Controls::default())
+    }
+
+    fn frame(&mut self, input: &mut dyn Input) -> Result<GameAction, Error> {
+        for key in input.keys() {
+            self.message = None;
+
+            if self.rebinding {
+                self.rebind(key);
+                continue;
+            }
+
+            match self.controls.menu(key) {
+                Some(MenuEvent::Down) => self.selected = (self.selected + 1) % self.items.len(),
+                Some(MenuEvent::Up) => {
+                    self.selected = (self.selected + self.items.len() - 1) % self.items.len()
+                }
+                Some(MenuEvent::Select) => match self.items[self.selected] {
+                    ControlsItem::Preset => {
+                        let preset = self.preset.map_or(Preset::Arrows, Preset::next);
+                        self.controls = Controls::preset(preset);
+                        self.preset = Some(preset);
+                        self.changed = true;
+                    }
+                    ControlsItem::Back => return Ok(self.leave()),
+                    _ => self.rebinding = true,
+                },
+                Some(MenuEvent::Quit) => return Ok(self.leave()),
+                None => (),
+            }
+        }
+
+        Ok(GameAction::Controls)
+    }
+
+    fn draw(&self, out: &mut dyn Renderer) -> Result<(), Error> {
+        let mut lines = vec![(0, "CONTROLS".to_string())];
+
+        let width = self.items.iter().map(|item| item.label().len()).max().unwrap_or(0);
+
+        for (i, item) in self.items.iter().enumerate() {
+            let cursor = if i == self.selected { ">" } else { " " };
+            let value = match item {
+                _ if self.rebinding && i == self.selected => "press a key".to_string(),
+                ControlsItem::Game(event) => Self::key_names(self.game_keys(*event)),
+                ControlsItem::Menu(event) => Self::key_names(self.menu_keys(*event)),
+                ControlsItem::Preset => self.preset.map_or("Custom".to_string(), |p| p.to_string()),
+                ControlsItem::Back => String::new(),
+            };
+            let text = format!("{} {:<width$}  {:<12}", cursor, item.label(), value, width = width);
+            lines.push((2 + i, text));
+        }
+
+        let hint = match &self.message {
+            Some(message) => message.clone(),
+            None if self.rebinding => "Ctrl-C to cancel".to_string(),
+            None => "Select to rebind".to_string(),
+        };
+        lines.push((self.screen.size().1.saturating_sub(1), hint));
+
+        out.draw_text(&self.screen, &lines)
+    }
+}
+
+impl ControlsLoop {
+    pub fn new(screen: Screen, controls: Controls) -> Self {
+        let items = controls
+            .game_bindings()
+            .iter()
+            .map(|(event, _)| ControlsItem::Game(*event))
+            .chain(controls.menu_bindings().iter().map(|(event, _)| ControlsItem::Menu(*event)))
+            .chain(vec![ControlsItem::Preset, ControlsItem::Back])
+            .collect();
+
+        Self {
+            screen,
+            controls,
+            items,
+            selected: 0,
+            preset: None,
+            rebinding: false,
+            changed: false,
+            in_game: false,
+            message: None,
+        }
+    }
+
+    /// Goes back to the pause menu when done, instead of the main menu.
+    pub fn in_game(mut self) -> Self {
+        self.in_game = true;
+        self
+    }
+
+    pub fn controls(&self) -> &Controls {
+        &self.controls
+    }
+
+    fn rebind(&mut self, key: Key) {
+        self.rebinding = false;
+
+        // Ctrl-C is always quit, so it can't be bound to anything else
+        if key == Key::Ctrl('c') {
+            return;
+        }
+
+        let bound = match self.items[self.selected] {
+            ControlsItem::Game(event) => self.controls.bind_game(event, key),
+            ControlsItem::Menu(event) => self.controls.bind_menu(event, key),
+            _ => return,
+        };
+
+        match bound {
+            Ok(()) => {
+                self.preset = None;
+                self.changed = true;
+            }
+            Err(e) => self.message = Some(e.to_string()),
+        }
+    }
+
+    fn leave(&self) -> GameAction {
+        if self.changed {
+            if let Err(e) = self.controls.save() {
+                error!("Failed to save controls: {}", e);
+            }
+        }
+
+        if self.in_game {
+            GameAction::Pause
+        } else {
+            GameAction::Menu
+        }
+    }
+
+    fn game_keys(&self, event: GameEvent) -> &[Key] {
+        self.controls
+            .game_bindings()
+            .iter()
+            .find(|(bound, _)| *bound == event)
+            .map_or(&[], |(_, keys)| keys)
+    }
+
+    fn menu_keys(&self, event: MenuEvent) -> &[Key] {
+        self.controls
+            .menu_bindings()
+            .iter()
+            .find(|(bound, _)| *bound == event)
+            .map_or(&[], |(_, keys)| keys)
+    }
+
+    fn key_names(keys: &[Key]) -> String {
+        if keys.is_empty() {
+            return "-".to_string();
+        }
+
+        keys.iter().map(|key| key.to_string()).collect::<Vec<_>>().join(", ")
+    }
+}
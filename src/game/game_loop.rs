@@ -1,38 +1,148 @@
-use crate::entities::{Entity, Invader, Missile, Player};
+use crate::bot;
+use crate::clock::TickRate;
+use crate::controls::Controls;
+use crate::entities::{Bunker, Entity, Invader, InvaderKind, Missile, Player, Ufo};
+use crate::level::Level;
 use crate::map::Map;
-use crate::utils::{Coord, Dir, Screen};
+use crate::score::Score;
+use crate::utils::{Coord, Dir, Screen, Tile};
 use crate::game::Loop;
 use crate::game::GameAction;
+use crate::game::swarm::Swarm;
+use crate::input::Input;
+use crate::render::{Hud, Renderer};
+use crate::replay::Replay;
+use crate::save::{self, SavedGame};
 use failure::Error;
-use std::clone::Clone;
-use std::io::{Stdout, Write};
-use termion::cursor::Goto;
-use termion::event::Key;
-use termion::input::TermRead;
-use termion::raw::RawTerminal;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+use serde::{Deserialize, Serialize};
 
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum CtrlEvent {
     Left,
     Right,
     Shoot,
+    /// Handled before the game steps, so never recorded.
+    Pause,
+    Quit,
+}
+
+#[derive(Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FirePattern {
+    /// A random invader from the bottom of its column fires.
+    Random,
+    /// The bottom invader closest to the player's column fires.
+    Aimed,
+    /// Every bottom invader fires at once.
+    Volley,
+}
+
+/// Decides when the invaders fire, and which pattern they use.
+/// The patterns are cycled through in order, one every `interval` ticks.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FireSchedule {
+    pub patterns: Vec<FirePattern>,
+    pub interval: u32,
+    next: usize,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum UfoEvent {
+    Appeared,
+    Escaped,
+    Destroyed(u32),
 }
 
 pub struct FrameState {
     pub events: Vec<CtrlEvent>,
     pub screen: Screen,
-    pub frame: u8,
+    /// Ticks since the wave started.
+    pub frame: u32,
+}
+
+// All the timings are in seconds, and speeds in cells a second, so the game
+// plays the same at any tick rate. `Ticks` turns them into ticks.
+
+/// How long the "Wave N" screen is shown for between waves.
+const INTERSTITIAL_TIME: f32 = 2.0;
+/// Invaders never fire faster than this, however many waves in.
+const MIN_FIRE_INTERVAL: f32 = 0.18;
+/// How much sooner the invaders fire on each lap through the levels.
+const LAP_FIRE_SPEEDUP: f32 = 0.06;
+/// How many more steps a second the swarm takes on each lap.
+const LAP_SWARM_SPEEDUP: f32 = 1.5;
+/// Range of time to wait between UFO sightings.
+const UFO_INTERVAL: (f32, f32) = (9.0, 21.0);
+const UFO_POINTS: &[u32] = &[50, 100, 150, 300];
+/// How long a UFO event stays on the HUD.
+const UFO_EVENT_TIME: f32 = 1.5;
+/// Each diver peels off to dive about this often.
+const DIVE_TIME: f32 = 1.2;
+const DIVE_SPEED: f32 = 16.0;
+/// Each shooter with a clear shot fires a burst about this often.
+const BURST_TIME: f32 = 1.8;
+const MISSILE_SPEED: f32 = 33.0;
+/// Time between the player's shots.
+const RELOAD_TIME: f32 = 0.18;
+/// How long the player blinks on and off for while invulnerable.
+const BLINK_TIME: f32 = 0.1;
+
+/// The timings above, in ticks at the game's tick rate.
+struct Ticks {
+    interstitial: u32,
+    ufo_interval: (u32, u32),
+    ufo_event: u32,
+    ufo_step: u32,
+    dive_chance: u32,
+    dive_step: u32,
+    burst_chance: u32,
+    missile_step: u32,
+    reload: u32,
+    invulnerable: u32,
+    blink: u32,
 }
 
 pub struct GameLoop {
     // Game Logic stuff
     is_running: bool,
+    /// Stop before the next step, and hand over to the pause menu.
+    pause_requested: bool,
+    controls: Controls,
+    /// Let the bot play, instead of reading the keys.
+    autopilot: bool,
+    /// Every random decision comes from here, so a game can be replayed from its seed.
+    rng: Pcg32,
+    seed: u64,
     screen: Screen,
-    frame: u8,
+    /// Ticks since the wave started.
+    frame: u32,
+    /// Every step since the game started, for the recording.
+    tick: u32,
+    rate: TickRate,
+    ticks: Ticks,
+    recording: Replay,
+    playback: Option<Replay>,
+    score: Score,
+    wave: usize,
+    levels: Vec<Level>,
+    interstitial: u32,
 	
     // Entities
+    spawn: Coord,
     player: Player,
     invaders: Vec<Invader>,
+    swarm: Swarm,
     missiles: Vec<Missile>,
+    bunkers: Vec<Bunker>,
+    ufo: Option<Ufo>,
+    ufo_timer: u32,
+    ufo_event: Option<(UfoEvent, u32)>,
+    map: Map<Tile>,
+    fire_schedule: FireSchedule,
+    player_hit: bool,
 }
 
 impl FrameState {
@@ -45,125 +155,558 @@ impl FrameState {
     }
 }
 
+impl Ticks {
+    fn new(rate: TickRate) -> Self {
+        Self {
+            interstitial: rate.ticks(INTERSTITIAL_TIME),
+            ufo_interval: (rate.ticks(UFO_INTERVAL.0), rate.ticks(UFO_INTERVAL.1)),
+            ufo_event: rate.ticks(UFO_EVENT_TIME),
+            ufo_step: rate.interval(Ufo::SPEED),
+            dive_chance: rate.ticks(DIVE_TIME),
+            dive_step: rate.interval(DIVE_SPEED),
+            burst_chance: rate.ticks(BURST_TIME),
+            missile_step: rate.interval(MISSILE_SPEED),
+            reload: rate.ticks(RELOAD_TIME),
+            invulnerable: rate.ticks(Player::INVULNERABLE_TIME),
+            blink: rate.ticks(BLINK_TIME),
+        }
+    }
+}
+
+impl FireSchedule {
+    pub fn new(patterns: Vec<FirePattern>, interval: u32) -> Self {
+        Self {
+            patterns,
+            interval,
+            next: 0,
+        }
+    }
+
+    fn next_pattern(&mut self) -> Option<&FirePattern> {
+        if self.patterns.is_empty() {
+            return None;
+        }
+
+        let index = self.next % self.patterns.len();
+        self.next = index + 1;
+        self.patterns.get(index)
+    }
+}
+
 impl Loop<'_> for GameLoop {
     fn init(screen: Screen) -> Self {
-        // TODO move to level file
-        let map_size = screen.size();
-        let player_pos = Coord(map_size.0 / 2, map_size.1 - 1);
-
-        let invader1 = Invader::new(Coord(2, 2), Dir::Right);
-        let invader2 = Invader::new(Coord(3, 2), Dir::Right);
-        let invader3 = Invader::new(Coord(4, 2), Dir::Right);
-        let invader4 = Invader::new(Coord(5, 2), Dir::Right);
-
-        let invader5 = Invader::new(Coord(7, 2), Dir::Right);
-        let invader6 = Invader::new(Coord(8, 2), Dir::Right);
-        let invader7 = Invader::new(Coord(9, 2), Dir::Right);
-        let invader8 = Invader::new(Coord(10, 2), Dir::Right);
-
-        let invader9 = Invader::new(Coord(12, 2), Dir::Right);
-        let invader10 = Invader::new(Coord(13, 2), Dir::Right);
-        let invader11 = Invader::new(Coord(14, 2), Dir::Right);
-        let invader12 = Invader::new(Coord(15, 2), Dir::Right);
-
-        let invader13 = Invader::new(Coord(17, 2), Dir::Right);
-        let invader14 = Invader::new(Coord(18, 2), Dir::Right);
-        let invader15 = Invader::new(Coord(19, 2), Dir::Right);
-        let invader16 = Invader::new(Coord(20, 2), Dir::Right);
-
-        let invaders = vec![
-            invader1, invader2, invader3, invader4, invader5, invader6, invader7, invader8,
-            invader9, invader10, invader11, invader12, invader13, invader14, invader15, invader16,
-        ];
+        Self::with_levels(screen, Level::defaults(), rand::random(), TickRate::default())
+    }
 
-        Self {
+    fn frame(&mut self, input: &mut dyn Input) -> Result<GameAction, Error> {
+        let mut events = Vec::new();
+        self.handle_input(input, &mut events);
+
+        // Freeze right where we are, nothing moves until we're back
+        if self.pause_requested {
+            self.pause_requested = false;
+            return Ok(GameAction::Pause);
+        }
+
+        // Played back and bot games ignore the player, short of quitting or pausing
+        let action = match &self.playback {
+            Some(playback) if self.tick >= playback.length() => {
+                self.is_running = false;
+                GameAction::Continue
+            }
+            Some(playback) => {
+                let events = playback.events_at(self.tick).to_vec();
+                self.step(events)
+            }
+            None if self.autopilot => {
+                let events = bot::commands(self);
+                self.step(events)
+            }
+            None => self.step(events),
+        };
+
+        match action {
+            GameAction::Continue if !self.is_running => {
+                self.is_running = true;
+                Ok(GameAction::Menu)
+            }
+            action => Ok(action),
+        }
+    }
+
+    fn draw(&self, output: &mut dyn Renderer) -> Result<(), Error> {
+        if self.interstitial > 0 {
+            let title_y = self.screen.size().1 / 2 - 1;
+            let level = &self.levels[(self.wave - 1) % self.levels.len()];
+            let lines = vec![
+                (title_y, format!("WAVE {}", self.wave)),
+                (title_y + 2, level.name.clone()),
+            ];
+
+            return output.draw_text(&self.screen, &lines);
+        }
+
+        let map = &self.map;
+        let mut glyphs = Map::new(map.dimensions, ' ');
+        let mut draw = |entity: &dyn Entity| {
+            for (Coord(x, y), glyph) in entity.cells() {
+                if x < glyphs.width() && y < glyphs.height() {
+                    glyphs[(x, y)] = glyph;
+                }
+            }
+        };
+
+        self.bunkers.iter().for_each(|bunker| draw(bunker));
+        self.invaders.iter().for_each(|invader| draw(invader));
+        self.missiles.iter().for_each(|missile| draw(missile));
+        if let Some(ufo) = &self.ufo {
+            draw(ufo);
+        }
+
+        // Blink while invulnerable
+        if self.player.invulnerable % (2 * self.ticks.blink) < self.ticks.blink {
+            draw(&self.player);
+        }
+
+        // Whatever collided is lost in the blast
+        for (glyph, tile) in glyphs.grid.iter_mut().zip(map.grid.iter()) {
+            if *tile == Tile::Explosion {
+                *glyph = '*';
+            }
+        }
+
+        let message = self.ufo_event.as_ref().map(|(event, _)| match event {
+            UfoEvent::Appeared => "UFO spotted!".to_string(),
+            UfoEvent::Escaped => "UFO escaped".to_string(),
+            UfoEvent::Destroyed(points) => format!("UFO down! +{}", points),
+        });
+
+        let hud = Hud {
+            score: self.score.total(),
+            multiplier: self.score.multiplier(),
+            lives: self.player.lives,
+            wave: self.wave,
+            message,
+        };
+
+        output.draw_game(&self.screen, map, &glyphs, &hud)
+    }
+}
+
+impl GameLoop {
+    /// A new game with the given levels, for running without a terminal.
+    /// The same seed and commands always play out the same way. Fails if
+    /// there are no levels.
+    pub fn new(levels: Vec<Level>, seed: u64) -> Result<Self, Error> {
+        let size = levels.first().ok_or_else(|| format_err!("There are no levels to play"))?.size();
+        Ok(Self::with_levels(Screen::new(size), levels, seed, TickRate::default()))
+    }
+
+    /// Only for the game itself, which always has levels. Panics if `levels` is empty.
+    pub(crate) fn with_levels(screen: Screen, levels: Vec<Level>, seed: u64, rate: TickRate) -> Self {
+        let spawn = levels[0].player_start();
+        let recording = Replay::new(seed, &levels[0], rate);
+
+        let mut rv = Self {
             screen,
-            invaders,
+            rng: Pcg32::seed_from_u64(seed),
+            seed,
+            invaders: Vec::new(),
+            swarm: Swarm::new(1, 0),
             frame: 0,
+            tick: 0,
+            rate,
+            ticks: Ticks::new(rate),
+            recording,
+            playback: None,
+            score: Score::default(),
+            wave: 0,
+            levels,
+            interstitial: 0,
             is_running: true,
-            player: Player::new(player_pos),
+            pause_requested: false,
+            controls: Controls::default(),
+            autopilot: false,
+            spawn,
+            player: Player::new(spawn),
             missiles: Vec::new(),
+            bunkers: Vec::new(),
+            ufo: None,
+            ufo_timer: 0,
+            ufo_event: None,
+            map: Map::new(*screen.size(), Tile::None),
+            fire_schedule: FireSchedule::new(Vec::new(), 0),
+            player_hit: false,
+        };
+
+        rv.next_wave();
+        rv
+    }
+
+    /// A game that plays back a recording, on the levels it was recorded on.
+    pub fn with_replay(screen: Screen, levels: Vec<Level>, replay: Replay) -> Result<Self, Error> {
+        let levels = replay.arrange_levels(levels)?;
+
+        let mut rv = Self::with_levels(screen, levels, replay.seed(), replay.rate());
+        rv.playback = Some(replay);
+        Ok(rv)
+    }
+
+    /// Picks a saved game back up where it left off. `levels` are the
+    /// ones available, the save says which of them it was played on.
+    pub fn from_save(screen: Screen, levels: &[Level], saved: SavedGame) -> Result<Self, Error> {
+        let levels = saved.arrange_levels(levels)?;
+        let rate = TickRate::new(saved.rate)?;
+        let recording = Replay::parse(&saved.recording)
+            .map_err(|e| format_err!("The save's recording is broken: {}", e))?;
+
+        if levels.is_empty() || saved.wave == 0 {
+            return Err(format_err!("The save has no wave in progress"));
+        }
+
+        let size = levels[(saved.wave - 1) % levels.len()].size();
+        saved.check(size)?;
+
+        let mut rv = Self::with_levels(screen, levels, saved.seed, rate);
+
+        rv.rng = saved.rng;
+        rv.screen = Screen::new(size);
+        rv.frame = saved.frame;
+        rv.tick = saved.tick;
+        rv.recording = recording;
+        rv.score = saved.score;
+        rv.wave = saved.wave;
+        rv.interstitial = saved.interstitial;
+        rv.spawn = saved.spawn;
+        rv.player = saved.player;
+        rv.invaders = saved.invaders;
+        rv.swarm = saved.swarm;
+        rv.missiles = saved.missiles;
+        rv.bunkers = saved.bunkers;
+        rv.ufo = saved.ufo;
+        rv.ufo_timer = saved.ufo_timer;
+        let timer = saved.ufo_event_timer;
+        rv.ufo_event = saved.ufo_event.map(|event| (event, timer));
+        rv.fire_schedule = saved.fire_schedule;
+        rv.player_hit = saved.player_hit;
+        // Only for drawing, the next step fills it in again
+        rv.map = Map::new(size, Tile::None);
+
+        Ok(rv)
+    }
+
+    /// Everything needed to pick the game up again later.
+    pub fn to_save(&self) -> SavedGame {
+        SavedGame {
+            version: save::VERSION,
+            seed: self.seed,
+            rng: self.rng.clone(),
+            rate: self.rate.per_second(),
+            levels: self.levels.iter().map(|level| level.name.clone()).collect(),
+            rules: self.levels[0].rules,
+            wave: self.wave,
+            frame: self.frame,
+            tick: self.tick,
+            interstitial: self.interstitial,
+            score: self.score.clone(),
+            spawn: self.spawn,
+            player: self.player.clone(),
+            invaders: self.invaders.clone(),
+            swarm: self.swarm.clone(),
+            missiles: self.missiles.clone(),
+            bunkers: self.bunkers.clone(),
+            ufo: self.ufo.clone(),
+            ufo_timer: self.ufo_timer,
+            ufo_event: self.ufo_event.as_ref().map(|(event, _)| *event),
+            ufo_event_timer: self.ufo_event.as_ref().map_or(0, |(_, timer)| *timer),
+            fire_schedule: self.fire_schedule.clone(),
+            player_hit: self.player_hit,
+            recording: self.recording.to_string(),
         }
     }
 
-    fn frame(&mut self, input: &mut termion::AsyncReader, out: &mut RawTerminal<Stdout>) -> Result<GameAction, Error> {
-        let mut frame_state = FrameState::new(self.screen);
+    /// Sets up the next wave, keeping the score and lives. Once every level
+    /// has been played they start over, only faster, meaner, and closer.
+    fn next_wave(&mut self) {
+        self.wave += 1;
+
+        let level = &self.levels[(self.wave - 1) % self.levels.len()];
+        let lap = (self.wave - 1) / self.levels.len();
+        info!("Starting wave {}: {}", self.wave, level.name);
+
+        self.screen = Screen::new(level.size());
+        self.map = Map::new(level.size(), Tile::None);
+        self.spawn = level.player_start();
 
+        self.fire_schedule = level.fire_schedule(self.rate);
+        if self.fire_schedule.interval > 0 {
+            let interval = level.fire_interval - LAP_FIRE_SPEEDUP * lap as f32;
+            self.fire_schedule.interval = self.rate.ticks(interval.max(MIN_FIRE_INTERVAL));
+        }
+
+        // Start lower each lap, but always leave some room above the player
+        let mut invaders = level.invaders();
+        let lowest = invaders.iter().map(|i| i.position.1).max().unwrap_or(0);
+        let shift = std::cmp::min(lap, self.ground().saturating_sub(lowest + 3));
+
+        for invader in invaders.iter_mut() {
+            invader.position.1 += shift;
+        }
+
+        let speed = level.swarm_speed + LAP_SWARM_SPEEDUP * lap as f32;
+        self.swarm = Swarm::new(self.rate.interval(speed), invaders.len());
+        self.invaders = invaders;
+        self.bunkers = level.bunkers();
+        self.missiles.clear();
+        self.ufo = None;
+        self.ufo_timer = Self::ufo_interval(&mut self.rng, &self.ticks);
+        self.player.position = self.spawn;
+        self.frame = 0;
+        self.interstitial = self.ticks.interstitial;
+    }
+
+    /// Advances the game by one frame, with the given player commands.
+    /// Returns `GameAction::GameOver` once the player is out of lives or
+    /// the invaders have landed, and `GameAction::Continue` otherwise.
+    pub fn step(&mut self, events: Vec<CtrlEvent>) -> GameAction {
+        self.recording.record(self.tick, &events);
+        self.tick += 1;
+
+        // Nothing moves while the "Wave N" screen is up
+        if self.interstitial > 0 {
+            self.interstitial -= 1;
+            return GameAction::Continue;
+        }
+
+        let mut frame_state = FrameState::new(self.screen);
         frame_state.frame = self.frame;
-        frame_state.events.clear();
+        frame_state.events = events;
 
-        self.handle_input(input, &mut frame_state.events);
         self.process_entities(&frame_state);
-        let map = self.handle_collisions();
-        self.draw(out, map)?;
+        self.map = self.handle_collisions();
 
-        if self.invaders.is_empty() {
-			return Ok(GameAction::EndGame);
+        if self.player_hit {
+            self.player_hit = false;
+
+            if self.player.kill() {
+                self.player.respawn(self.spawn, self.ticks.invulnerable);
+            } else {
+                return GameAction::GameOver;
+            }
         }
 
-		crate::utils::looped_inc(&mut self.frame);
-		
-        if self.is_running {
-            Ok(GameAction::Continue)
-        } else {
-			self.is_running = true;
-            Ok(GameAction::Menu)
+        // The swarm made it down to the player, it's all over
+        let ground = self.ground();
+        let landed = self
+            .invaders
+            .iter()
+            .any(|invader| !invader.diving && invader.position.1 >= ground);
+
+        if landed {
+            return GameAction::GameOver;
+        }
+
+        if self.invaders.is_empty() {
+            self.next_wave();
         }
+
+        self.frame += 1;
+
+        GameAction::Continue
     }
-}
 
-impl GameLoop {
+    /// Reads the keys with the given bindings, instead of the defaults.
+    pub fn set_controls(&mut self, controls: Controls) {
+        self.controls = controls;
+    }
 
-    fn handle_input(&mut self, input: &mut termion::AsyncReader, events: &mut Vec<CtrlEvent>) {
-        // TODO Is there a better way to do this?
-        use std::io::Error;
-        use termion::event::Event;
+    /// Hands the controls over to the bot.
+    pub fn set_autopilot(&mut self, autopilot: bool) {
+        self.autopilot = autopilot;
+    }
 
-        let input_events = input.events().collect::<Vec<Result<Event, Error>>>();
+    pub fn score(&self) -> &Score {
+        &self.score
+    }
 
-        for event in input_events {
-            match event {
-                Ok(event) => match event {
-                    Event::Key(c) => match c {
-                        Key::Char('q') => self.is_running = false,
-                        Key::Ctrl('c') => self.is_running = false,
-                        Key::Left => events.push(CtrlEvent::Left),
-                        Key::Right => events.push(CtrlEvent::Right),
-                        Key::Char(' ') => events.push(CtrlEvent::Shoot),
-                        _ => (),
-                    },
-                    _ => (),
-                },
-                Err(e) => error!("Stdin error: {}", e.to_string()),
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Everything the player has done so far, to save and play back later.
+    pub fn recording(&self) -> &Replay {
+        &self.recording
+    }
+
+    pub fn wave(&self) -> usize {
+        self.wave
+    }
+
+    pub fn frame_count(&self) -> u32 {
+        self.frame
+    }
+
+    /// Whether the "Wave N" screen is up, rather than the game itself.
+    pub fn is_between_waves(&self) -> bool {
+        self.interstitial > 0
+    }
+
+    pub fn screen(&self) -> &Screen {
+        &self.screen
+    }
+
+    /// What's in each cell, as of the last frame.
+    pub fn map(&self) -> &Map<Tile> {
+        &self.map
+    }
+
+    pub fn player(&self) -> &Player {
+        &self.player
+    }
+
+    pub fn invaders(&self) -> &[Invader] {
+        &self.invaders
+    }
+
+    pub fn missiles(&self) -> &[Missile] {
+        &self.missiles
+    }
+
+    pub fn bunkers(&self) -> &[Bunker] {
+        &self.bunkers
+    }
+
+    pub fn ufo(&self) -> Option<&Ufo> {
+        self.ufo.as_ref()
+    }
+
+    /// The top row of the player, once it's back where it started. The
+    /// swarm has landed once it gets this low.
+    fn ground(&self) -> usize {
+        self.spawn.1.saturating_sub(Player::SPRITE.origin.1)
+    }
+
+    fn ufo_interval(rng: &mut Pcg32, ticks: &Ticks) -> u32 {
+        rng.gen_range(ticks.ufo_interval.0, ticks.ufo_interval.1)
+    }
+
+    fn handle_input(&mut self, input: &mut dyn Input, events: &mut Vec<CtrlEvent>) {
+        for key in input.keys() {
+            match self.controls.game(key) {
+                Some(CtrlEvent::Quit) => self.is_running = false,
+                Some(CtrlEvent::Pause) => self.pause_requested = true,
+                Some(event) => events.push(event),
+                None => (),
             }
         }
     }
 
     fn process_entities(&mut self, frame_state: &FrameState) {
         // TODO This could be done in parallel.
-        Self::update_missiles(&mut self.missiles, frame_state);
-        let mut missiles = Self::update_invaders(&mut self.invaders, frame_state);
-        let missile = Self::update_player(&mut self.player, frame_state);
+        let missed = Self::update_missiles(&mut self.missiles, &self.ticks, frame_state);
+
+        if missed > 0 {
+            self.score.miss();
+        }
+
+        let mut missiles = Self::update_invaders(
+            &mut self.invaders,
+            &mut self.rng,
+            &mut self.swarm,
+            &mut self.fire_schedule,
+            self.player.position(),
+            &self.ticks,
+            frame_state,
+        );
+        let missile = Self::update_player(&mut self.player, &self.ticks, frame_state);
 
         if let Some(missile) = missile {
             missiles.push(missile);
         }
 
         self.missiles.append(&mut missiles);
+
+        if let Some(event) = self.update_ufo(frame_state) {
+            self.ufo_event = Some((event, self.ticks.ufo_event));
+        } else if let Some((_, timer)) = &mut self.ufo_event {
+            *timer = timer.saturating_sub(1);
+            if *timer == 0 {
+                self.ufo_event = None;
+            }
+        }
     }
 
-    fn update_missiles(missiles: &mut Vec<Missile>, frame_state: &FrameState) {
-        missiles.drain_filter(|missile: &mut Missile| match missile.direction {
+    /// Spawns the UFO when its timer runs out, and flies it across the top row.
+    /// It doesn't step like the invaders, it just keeps going until it's off the edge.
+    fn update_ufo(&mut self, frame_state: &FrameState) -> Option<UfoEvent> {
+        let width = frame_state.screen.size().0;
+
+        match &mut self.ufo {
+            None => {
+                self.ufo_timer = self.ufo_timer.saturating_sub(1);
+
+                if self.ufo_timer > 0 || width < Ufo::SPRITE.size().0 {
+                    return None;
+                }
+
+                let points = *UFO_POINTS.choose(&mut self.rng).unwrap_or(&UFO_POINTS[0]);
+                let ufo = if self.rng.gen() {
+                    Ufo::new(Coord(0, 0), Dir::Right, points)
+                } else {
+                    Ufo::new(Coord(width - Ufo::SPRITE.size().0, 0), Dir::Left, points)
+                };
+
+                self.ufo = Some(ufo);
+                Some(UfoEvent::Appeared)
+            }
+            Some(ufo) => {
+                if !frame_state.frame.is_multiple_of(self.ticks.ufo_step) {
+                    return None;
+                }
+
+                let escaped = match ufo.direction {
+                    Dir::Left if ufo.position.0 > 0 => {
+                        ufo.position.0 -= 1;
+                        false
+                    }
+                    Dir::Right if ufo.position.0 + ufo.width() < width => {
+                        ufo.position.0 += 1;
+                        false
+                    }
+                    _ => true,
+                };
+
+                if escaped {
+                    self.ufo = None;
+                    self.ufo_timer = Self::ufo_interval(&mut self.rng, &self.ticks);
+                    Some(UfoEvent::Escaped)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Moves the missiles, returning how many of the player's went off screen.
+    fn update_missiles(missiles: &mut Vec<Missile>, ticks: &Ticks, frame_state: &FrameState) -> usize {
+        let mut missed = 0;
+
+        if !frame_state.frame.is_multiple_of(ticks.missile_step) {
+            return missed;
+        }
+
+        missiles.retain_mut(|missile: &mut Missile| !match missile.direction {
             Dir::Up => {
                 if missile.position.1 > 0 {
                     missile.position.1 -= 1;
                     false
                 } else {
+                    missed += 1;
                     true
                 }
             }
             Dir::Down => {
-                if missile.position.1 < frame_state.screen.size().1 {
+                if missile.position.1 < frame_state.screen.size().1 - 1 {
                     missile.position.1 += 1;
                     false
                 } else {
@@ -179,183 +722,469 @@ impl GameLoop {
                 false
             }
         });
+
+        missed
     }
 
-    fn update_player(player: &mut Player, frame_state: &FrameState) -> Option<Missile> {
-        player.missile_timer = std::cmp::min(player.missile_timer + 1, 254);
+    fn update_player(player: &mut Player, ticks: &Ticks, frame_state: &FrameState) -> Option<Missile> {
+        player.missile_timer = player.missile_timer.saturating_add(1);
+        player.invulnerable = player.invulnerable.saturating_sub(1);
 
         let mut request = None;
+        let (left, right) = player.sprite().reach();
 
         // Handle user inputs
         for event in frame_state.events.iter() {
             match event {
                 CtrlEvent::Left => {
-                    if player.position.0 > 0 {
+                    if player.position.0 > left {
                         player.position.0 -= 1
                     }
                 }
                 CtrlEvent::Right => {
-                    if player.position.0 < (frame_state.screen.size().0 - 1) {
+                    if player.position.0 + right < (frame_state.screen.size().0 - 1) {
                         player.position.0 += 1
                     }
                 }
+                // From just above the top of the cannon
                 CtrlEvent::Shoot => {
-                    if player.missile_timer > 5 {
+                    if player.missile_timer >= ticks.reload && player.top() > 0 {
                         player.missile_timer = 0;
-                        let mut pos = player.position.clone();
-                        pos.1 -= 1;
+                        let pos = Coord(player.position.0, player.top() - 1);
                         request = Some(Missile::new(pos, Dir::Up))
                     }
                 }
+                // Dealt with by handle_input, they never reach the player
+                CtrlEvent::Pause | CtrlEvent::Quit => (),
             }
         }
 
         request
     }
 
-    fn update_invaders(invaders: &mut Vec<Invader>, frame_state: &FrameState) -> Vec<Missile> {
-        let rv = Vec::with_capacity(0);
-
-        // TODO The invaders should fire back!!!
-
-        if frame_state.frame % 5 == 0 {
-            for invader in invaders {
-                match invader.direction {
-                    Dir::Down => {
-                        if invader.position.0 < (frame_state.screen.size().0 - invader.position.0)
-                        {
-                            // Closer to left edge
-                            invader.direction = Dir::Right;
-                            invader.position.0 += 1;
-                        } else {
-                            // Closer to right edge
-                            invader.direction = Dir::Left;
-                            invader.position.0 -= 1;
-                        }
-                    }
-                    Dir::Left => {
-                        if invader.position.0 == 0 {
-                            invader.direction = Dir::Down;
-                            invader.position.1 += 1;
-                        } else {
-                            invader.position.0 -= 1;
-                        }
-                    }
-                    Dir::Right => {
-                        if invader.position.0 == (frame_state.screen.size().0 - 1) {
-                            invader.direction = Dir::Down;
-                            invader.position.1 += 1;
-                        } else {
-                            invader.position.0 += 1;
-                        }
-                    }
-                    Dir::Up => {
-                        // TODO Log error.
-                        panic!("Invader with Dir::Up direction should not exist.")
-                    }
+    fn update_invaders(
+        invaders: &mut [Invader],
+        rng: &mut Pcg32,
+        swarm: &mut Swarm,
+        schedule: &mut FireSchedule,
+        target: &Coord,
+        ticks: &Ticks,
+        frame_state: &FrameState,
+    ) -> Vec<Missile> {
+        let mut rv = Vec::with_capacity(0);
+        let height = frame_state.screen.size().1;
+
+        swarm.update(invaders, frame_state.screen.size().0);
+
+        // Divers peel off on their own, whenever they like
+        for invader in invaders.iter_mut().filter(|invader| !invader.diving) {
+            if invader.kind == InvaderKind::Diver && rng.gen_range(0, ticks.dive_chance) == 0 {
+                invader.diving = true;
+            }
+        }
+
+        // Divers swoop down towards the player, then wrap back around to the top
+        if frame_state.frame.is_multiple_of(ticks.dive_step) {
+            for invader in invaders.iter_mut().filter(|invader| invader.diving) {
+                if invader.position.1 >= height - 1 {
+                    invader.position.1 = 1;
+                    invader.diving = false;
+                    continue;
+                }
+
+                invader.position.1 += 1;
+                invader.steps = invader.steps.wrapping_add(1);
+
+                if invader.position.0 < target.0 {
+                    invader.position.0 += 1;
+                } else if invader.position.0 > target.0 {
+                    invader.position.0 -= 1;
                 }
             }
         }
 
+        // Fire after moving, so the invaders don't step onto their own missiles.
+        if schedule.interval > 0 && frame_state.frame.is_multiple_of(schedule.interval) {
+            let shooters = Self::shooters(invaders);
+            let bottom = frame_state.screen.size().1 - 1;
+
+            let shooters = match schedule.next_pattern() {
+                Some(FirePattern::Random) => shooters
+                    .choose(rng)
+                    .into_iter()
+                    .cloned()
+                    .collect(),
+                Some(FirePattern::Aimed) => shooters
+                    .into_iter()
+                    .min_by_key(|shooter| {
+                        (shooter.position.0 as isize - target.0 as isize).abs()
+                    })
+                    .into_iter()
+                    .collect(),
+                Some(FirePattern::Volley) => shooters,
+                None => Vec::with_capacity(0),
+            };
+
+            for shooter in shooters {
+                if shooter.position.1 < bottom {
+                    let pos = Coord(shooter.position.0, shooter.position.1 + 1);
+                    rv.push(Missile::new(pos, Dir::Down));
+                }
+            }
+        }
+
+        // Shooters fire two missiles back to back, whenever they feel like it
+        for shooter in Self::shooters(invaders) {
+            let Coord(x, y) = shooter.position;
+
+            if shooter.kind == InvaderKind::Shooter
+                && y + 2 < height
+                && rng.gen_range(0, ticks.burst_chance) == 0
+            {
+                rv.push(Missile::new(Coord(x, y + 1), Dir::Down));
+                rv.push(Missile::new(Coord(x, y + 2), Dir::Down));
+            }
+        }
+
         rv
     }
 
-    fn handle_collisions(&mut self) -> Map<crate::utils::Tile> {
-        use crate::utils::Tile;
+    /// The invaders at the bottom of their columns, i.e. the ones with a clear shot.
+    fn shooters(invaders: &[Invader]) -> Vec<&Invader> {
+        let mut rv: Vec<&Invader> = Vec::new();
+
+        for invader in invaders {
+            match rv.iter_mut().find(|s| s.position.0 == invader.position.0) {
+                Some(shooter) => {
+                    if invader.position.1 > shooter.position.1 {
+                        *shooter = invader;
+                    }
+                }
+                None => rv.push(invader),
+            }
+        }
+
+        rv
+    }
 
-        let mut map = Map::<Tile>::new(self.screen.size().clone(), Tile::None);
+    /// Works out what's in each cell, dealing with anything that collided
+    /// along the way. Everything takes up every cell its sprite draws in.
+    fn handle_collisions(&mut self) -> Map<Tile> {
+        let mut map = Map::<Tile>::new(*self.screen.size(), Tile::None);
+        let size = map.dimensions;
+        let on_map = move |pos: &Coord| pos.0 < size.0 && pos.1 < size.1;
 
         for (index, missile) in self.missiles.iter().enumerate() {
-            let pos = missile.position();
-            map[pos] = Tile::Missile(index);
+            for pos in missile.footprint().iter().filter(|pos| on_map(pos)) {
+                map[pos] = Tile::Missile(index);
+            }
         }
 
         for (index, invader) in self.invaders.iter().enumerate() {
-            let pos = invader.position();
-            map[pos] = match map[pos] {
-                Tile::None => Tile::Invader(index),
-                _ => Tile::Explosion,
+            for pos in invader.footprint().iter().filter(|pos| on_map(pos)) {
+                map[pos] = match map[pos] {
+                    Tile::None => Tile::Invader(index),
+                    // Divers fly straight through the formation
+                    Tile::Invader(i) => Tile::Invader(i),
+                    _ => Tile::Explosion,
+                }
+            }
+        }
+
+        // Only the player can hit the UFO, anything else just passes through
+        if let Some(ufo) = &self.ufo {
+            let mut hit = false;
+
+            for pos in ufo.footprint().iter().filter(|pos| on_map(pos)) {
+                map[pos] = match map[pos] {
+                    Tile::Missile(i) if self.missiles[i].direction == Dir::Up => {
+                        hit = true;
+                        Tile::Explosion
+                    }
+                    Tile::None => Tile::Ufo,
+                    tile => tile,
+                }
+            }
+
+            if hit {
+                self.score.bonus(ufo.points);
+                self.ufo_event = Some((UfoEvent::Destroyed(ufo.points), self.ticks.ufo_event));
+                self.ufo = None;
+                self.ufo_timer = Self::ufo_interval(&mut self.rng, &self.ticks);
+            }
+        }
+
+        // Bunkers soak up missiles, and get trampled by invaders
+        for (index, bunker) in self.bunkers.iter_mut().enumerate() {
+            for pos in bunker.footprint().iter().filter(|pos| on_map(pos)) {
+                map[pos] = match map[pos] {
+                    Tile::None => Tile::Bunker(index),
+                    Tile::Invader(i) => {
+                        bunker.health = 0;
+                        Tile::Invader(i)
+                    }
+                    Tile::Missile(i) => {
+                        if self.missiles[i].direction == Dir::Up {
+                            self.score.miss();
+                        }
+                        bunker.hit();
+                        Tile::Bunker(index)
+                    }
+                    tile => {
+                        bunker.hit();
+                        tile
+                    }
+                }
             }
         }
 
-        self.missiles
-            .retain(|missile| match map[missile.position()] {
-                Tile::Missile(_) => true,
-                _ => false,
-            });
+        self.bunkers.retain(|bunker| !bunker.is_destroyed());
+
+        for pos in self.player.footprint().iter().filter(|pos| on_map(pos)) {
+            map[pos] = match map[pos] {
+                Tile::None => Tile::Player,
+                _ if self.player.is_invulnerable() => Tile::Player,
+                _ => {
+                    self.player_hit = true;
+                    Tile::Explosion
+                }
+            };
+        }
+
+        let missiles = &self.missiles;
+        let score = &mut self.score;
+        let mut children = Vec::new();
 
-        self.invaders
-            .retain(|invader| match map[invader.position()] {
-                Tile::Invader(_) => true,
-                _ => false,
-            });
+        self.invaders.retain_mut(|invader| {
+            let footprint = invader.footprint();
+
+            if footprint.iter().filter(|pos| on_map(pos)).all(|pos| matches!(map[pos], Tile::Invader(_))) {
+                return true;
+            }
 
-        let pos = self.player.position();
-        map[pos] = Tile::Player;
+            // Only the player's missiles are worth points, or can be shrugged off
+            let shot = missiles
+                .iter()
+                .any(|m| m.direction == Dir::Up && footprint.contains(&m.position));
+
+            if shot && invader.hit() {
+                return true;
+            }
+
+            if shot {
+                score.kill(invader.kind);
+            }
+
+            children.append(&mut invader.split());
+            false
+        });
+
+        let width = map.width();
+        children.retain(|child| child.position.0 < width);
+        self.invaders.append(&mut children);
+
+        self.missiles.retain(|missile| {
+            missile
+                .footprint()
+                .iter()
+                .filter(|pos| on_map(pos))
+                .all(|pos| matches!(map[pos], Tile::Missile(_)))
+        });
 
         map
     }
+}
 
-    fn draw(
-        &mut self,
-        output: &mut RawTerminal<Stdout>,
-        map: Map<crate::utils::Tile>,
-    ) -> Result<(), Error> {
-        use crate::utils::Tile;
-		use std::fmt::Write;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{Key, ScriptedInput};
+    use crate::render::HeadlessRenderer;
 
-		// TODO Reuse buffer to avoid reallocating every frame
-		let margins = self.screen.margins();
-		let mut buff = String::with_capacity(self.screen.frame_buffer_size());
-		let mut cursor = (margins.0 as u16, margins.1 as u16);
-        let dimensions = (map.width(), map.height());
+    fn draw(game: &GameLoop) -> Vec<String> {
+        let mut out = HeadlessRenderer::default();
+        game.draw(&mut out).unwrap();
+        out.frame().to_vec()
+    }
 
-        // Top border
-        write!(&mut buff, "{}{}+", termion::clear::All, Goto(cursor.0, cursor.1))?;
+    /// A game on the built-in levels, past the "Wave 1" screen.
+    fn started() -> GameLoop {
+        let mut game = GameLoop::new(Level::defaults(), 1).unwrap();
+        let mut input = ScriptedInput::new(Vec::new());
 
-        for _ in 0..dimensions.0 {
-            write!(&mut buff, "-")?;
-            cursor.0 += 1;
+        while game.is_between_waves() {
+            assert!(matches!(game.frame(&mut input).unwrap(), GameAction::Continue));
         }
 
-        cursor.0 = margins.0 as u16;
-        cursor.1 += 1;
+        game
+    }
 
-        write!(&mut buff, "+{}", Goto(cursor.0, cursor.1))?;
+    #[test]
+    fn keeps_up_with_every_speed_at_the_slowest_tick_rate() {
+        let ticks = Ticks::new(TickRate::new(TickRate::MIN).unwrap());
 
-        // Contents
-        for y in 0..dimensions.1 {
-            write!(&mut buff, "|")?;
-            for x in 0..dimensions.0 {
-                let icon = match map[(x, y)] {
-                    Tile::Explosion => "*",
-                    Tile::Invader(_) => "@",
-                    Tile::Missile(_) => "!",
-                    Tile::Player => "^",
-                    Tile::None => " ",
-                };
+        assert_eq!(ticks.missile_step, 1);
+        assert!(ticks.dive_step >= 1 && ticks.ufo_step >= 1);
+        assert!(MISSILE_SPEED <= TickRate::MIN as f32);
+        assert!(DIVE_SPEED <= TickRate::MIN as f32 && Ufo::SPEED <= TickRate::MIN as f32);
+    }
+
+    #[test]
+    fn needs_levels_to_play() {
+        assert!(GameLoop::new(Vec::new(), 1).is_err());
+
+        let replay = GameLoop::new(Level::defaults(), 1).unwrap().recording().clone();
+        assert!(GameLoop::with_replay(Screen::new(Coord(45, 15)), Vec::new(), replay).is_err());
+    }
+
+    #[test]
+    fn shows_the_wave_before_it_starts() {
+        let game = GameLoop::new(Level::defaults(), 1).unwrap();
+        let frame = draw(&game);
+
+        assert!(frame.iter().any(|row| row.contains("WAVE 1")));
+        assert!(frame.iter().any(|row| row.contains("First Contact")));
+    }
+
+    #[test]
+    fn draws_the_field_in_a_border_with_the_hud_below() {
+        let game = started();
+        let frame = draw(&game);
+        let border = format!("+{}+", "-".repeat(45));
 
-                write!(&mut buff, "{}", icon)?;
+        assert_eq!(frame[0], border);
+        assert_eq!(frame[16], border);
+        assert!(frame[1..16].iter().all(|row| row.starts_with('|') && row.ends_with('|')));
+        assert!(frame[17].starts_with("Score: 0"));
+        assert!(frame[17].contains("Lives: 3"));
+        assert!(frame[17].contains("Wave: 1"));
+
+        // The player sits at the bottom, in the middle
+        assert_eq!(&frame[14][22..25], " ^ ");
+        assert_eq!(&frame[15][22..25], "/_\\");
+    }
+
+    #[test]
+    fn moves_the_player_with_the_keys() {
+        let mut game = started();
+        let start = game.player().position.0;
+        let mut input = ScriptedInput::new(vec![vec![Key::Left], vec![Key::Left]]);
+
+        game.frame(&mut input).unwrap();
+        game.frame(&mut input).unwrap();
+
+        assert_eq!(game.player().position.0, start - 2);
+    }
+
+    /// A started game with nothing on the field but the player.
+    fn cleared() -> GameLoop {
+        let mut game = started();
+        game.invaders.clear();
+        game.bunkers.clear();
+        game.missiles.clear();
+        game.ufo = None;
+        game
+    }
+
+    #[test]
+    fn hits_a_sprite_anywhere_it_draws() {
+        let mut game = cleared();
+        game.ufo = Some(Ufo::new(Coord(10, 2), Dir::Right, 100));
+        // The "O" in the middle of "<=O=>", two cells right of its position
+        game.missiles.push(Missile::new(Coord(12, 2), Dir::Up));
+
+        let map = game.handle_collisions();
+
+        assert!(matches!(map[(12, 2)], Tile::Explosion));
+        assert!(game.ufo.is_none());
+        assert!(game.missiles.is_empty());
+        assert_eq!(game.score.total(), 100);
+    }
+
+    #[test]
+    fn misses_the_blanks_in_a_sprite() {
+        let mut game = cleared();
+        let Coord(x, y) = game.player().position;
+        // The top row of the cannon is " ^ ", so its corners are empty
+        game.missiles.push(Missile::new(Coord(x - 1, y - 1), Dir::Down));
+
+        let map = game.handle_collisions();
+
+        assert!(matches!(map[(x - 1, y - 1)], Tile::Missile(0)));
+        assert!(!game.player_hit);
+        assert_eq!(game.missiles.len(), 1);
+
+        // But its middle isn't
+        game.missiles[0].position = Coord(x, y - 1);
+        game.handle_collisions();
+
+        assert!(game.player_hit);
+        assert!(game.missiles.is_empty());
+    }
+
+    /// Some made up play, moving back and forth and shooting.
+    fn commands(tick: usize) -> Vec<CtrlEvent> {
+        match tick % 7 {
+            0 => vec![CtrlEvent::Shoot],
+            1 | 2 => vec![CtrlEvent::Left],
+            4 | 5 => vec![CtrlEvent::Right, CtrlEvent::Shoot],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Everything about the game's state, RNG included.
+    fn state(game: &GameLoop) -> toml::Value {
+        toml::Value::try_from(game.to_save()).unwrap()
+    }
+
+    #[test]
+    fn plays_the_same_with_the_same_seed_and_commands() {
+        let mut first = GameLoop::new(Level::defaults(), 42).unwrap();
+        let mut second = GameLoop::new(Level::defaults(), 42).unwrap();
+
+        for tick in 0..3000 {
+            let one = first.step(commands(tick));
+            let two = second.step(commands(tick));
+
+            assert_eq!(state(&first), state(&second), "Games differ after tick {}", tick);
+            assert_eq!(matches!(one, GameAction::GameOver), matches!(two, GameAction::GameOver));
+            if matches!(one, GameAction::GameOver) {
+                break;
             }
-            write!(
-                &mut buff,
-                "|{}",
-                Goto(margins.0 as u16, margins.1 as u16 + y as u16 + 1)
-            )?;
         }
 
-        // Bottom border
-        write!(&mut buff, "+")?;
-        for _ in 0..dimensions.0 {
-            write!(&mut buff, "-")?;
+        assert!(first.score().total() > 0);
+    }
+
+    #[test]
+    fn plays_back_a_recording_the_same() {
+        let mut game = GameLoop::new(Level::defaults(), 7).unwrap();
+
+        for tick in 0..1500 {
+            if matches!(game.step(commands(tick)), GameAction::GameOver) {
+                break;
+            }
         }
-        write!(&mut buff, "+")?;
 
-        write!(&mut buff, "{}", Goto(1, 1))?;
+        let replay = Replay::parse(&game.recording().to_string()).unwrap();
+        let length = replay.length();
+        let mut playback = GameLoop::with_replay(*game.screen(), Level::defaults(), replay).unwrap();
+        let mut input = ScriptedInput::new(Vec::new());
+
+        for _ in 0..length {
+            playback.frame(&mut input).unwrap();
+        }
+
+        assert_eq!(state(&playback), state(&game));
+    }
 
-		write!(output, "{}", buff)?;
-        output.flush().unwrap();
+    #[test]
+    fn pauses_without_stepping() {
+        let mut game = started();
+        let frame = game.frame_count();
+        let mut input = ScriptedInput::new(vec![vec![Key::Char('p')]]);
 
-        Ok(())
+        assert!(matches!(game.frame(&mut input).unwrap(), GameAction::Pause));
+        assert_eq!(game.frame_count(), frame);
     }
 }
@@ -0,0 +1,61 @@
+use crate::clock::TickRate;
+use crate::game::GameAction;
+use crate::game::Loop;
+use crate::input::Input;
+use crate::render::Renderer;
+use crate::utils::Screen;
+use failure::Error;
+
+/// How long to ignore input for, so a held key doesn't skip the screen.
+const INPUT_DELAY: f32 = 1.0;
+
+pub struct GameOverLoop {
+    screen: Screen,
+    frame: u32,
+    /// `INPUT_DELAY` in ticks.
+    delay: u32,
+}
+
+impl Loop<'_> for GameOverLoop {
+    fn init(screen: Screen) -> Self {
+        Self {
+            screen,
+            frame: 0,
+            delay: TickRate::default().ticks(INPUT_DELAY),
+        }
+    }
+
+    fn frame(&mut self, input: &mut dyn Input) -> Result<GameAction, Error> {
+        let pressed = self.handle_input(input);
+        self.frame = self.frame.saturating_add(1);
+
+        if pressed && self.frame > self.delay {
+            Ok(GameAction::EndGame)
+        } else {
+            Ok(GameAction::GameOver)
+        }
+    }
+
+    fn draw(&self, out: &mut dyn Renderer) -> Result<(), Error> {
+        let title_y = self.screen.size().1 / 2;
+        let mut lines = vec![(title_y, "GAME OVER".to_string())];
+
+        if self.frame > self.delay {
+            lines.push((title_y + 2, "Press any key".to_string()));
+        }
+
+        out.draw_text(&self.screen, &lines)
+    }
+}
+
+impl GameOverLoop {
+    pub fn with_tick_rate(mut self, rate: TickRate) -> Self {
+        self.delay = rate.ticks(INPUT_DELAY);
+        self
+    }
+
+    /// Drains the input, returning whether any key was pressed.
+    fn handle_input(&mut self, input: &mut dyn Input) -> bool {
+        !input.keys().is_empty()
+    }
+}
@@ -0,0 +1,59 @@
+use crate::game::GameAction;
+use crate::game::Loop;
+use crate::input::Input;
+use crate::high_scores::HighScores;
+use crate::render::Renderer;
+use crate::utils::Screen;
+use failure::Error;
+
+pub struct HighScoresLoop {
+    screen: Screen,
+    scores: HighScores,
+}
+
+impl Loop<'_> for HighScoresLoop {
+    fn init(screen: Screen) -> Self {
+        Self {
+            screen,
+            scores: HighScores::load_or_default(),
+        }
+    }
+
+    fn frame(&mut self, input: &mut dyn Input) -> Result<GameAction, Error> {
+        if self.handle_input(input) {
+            Ok(GameAction::Menu)
+        } else {
+            Ok(GameAction::HighScores)
+        }
+    }
+
+    fn draw(&self, out: &mut dyn Renderer) -> Result<(), Error> {
+        let mut lines = vec![(2, "HIGH SCORES".to_string())];
+
+        if self.scores.entries().is_empty() {
+            lines.push((4, "No scores yet".to_string()));
+        }
+
+        for (rank, entry) in self.scores.entries().iter().enumerate() {
+            lines.push((
+                4 + rank,
+                format!(
+                    "{:>2}. {:<3} {:>8}  #{}",
+                    rank + 1,
+                    entry.name,
+                    entry.score,
+                    entry.seed.map_or("-".to_string(), |seed| seed.to_string())
+                ),
+            ));
+        }
+
+        out.draw_text(&self.screen, &lines)
+    }
+}
+
+impl HighScoresLoop {
+    /// Drains the input, returning whether any key was pressed.
+    fn handle_input(&mut self, input: &mut dyn Input) -> bool {
+        !input.keys().is_empty()
+    }
+}
@@ -1,13 +1,12 @@
+use crate::controls::Controls;
 use crate::game::GameAction;
 use crate::game::Loop;
+use crate::render::Renderer;
 use crate::utils::Screen;
-use termion::event::Key;
+use crate::input::Input;
 use failure::Error;
-use termion::input::TermRead;
-use std::io::{Stdout, Write};
-use termion::cursor::Goto;
-use termion::raw::RawTerminal;
 
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum CtrlEvent {
 	Up,
 	Down,
@@ -15,18 +14,45 @@ pub enum CtrlEvent {
 	Quit,
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy)]
 pub enum MenuItem {
     NewGame,
     Continue,
+    LoadGame,
+    HighScores,
+    Controls,
     Quit,
 }
 
 impl MenuItem {
+	/// Every item, in the order they're shown.
+	pub const ALL: [MenuItem; 6] = [
+		MenuItem::Continue,
+		MenuItem::NewGame,
+		MenuItem::LoadGame,
+		MenuItem::HighScores,
+		MenuItem::Controls,
+		MenuItem::Quit,
+	];
+
+	pub fn label(&self) -> &'static str {
+		match self {
+			MenuItem::Continue => "Continue",
+			MenuItem::NewGame => "New Game",
+			MenuItem::LoadGame => "Load Game",
+			MenuItem::HighScores => "High Scores",
+			MenuItem::Controls => "Controls",
+			MenuItem::Quit => "Quit",
+		}
+	}
+
 	pub fn next(&self) -> Self {
 		match self {
 			MenuItem::Continue => Self::NewGame,
-			MenuItem::NewGame => Self::Quit,
+			MenuItem::NewGame => Self::LoadGame,
+			MenuItem::LoadGame => Self::HighScores,
+			MenuItem::HighScores => Self::Controls,
+			MenuItem::Controls => Self::Quit,
 			MenuItem::Quit => Self::Continue,
 		}
 	}
@@ -35,7 +61,10 @@ impl MenuItem {
 		match self {
 			MenuItem::Continue => Self::Quit,
 			MenuItem::NewGame => Self::Continue,
-			MenuItem::Quit => Self::NewGame,
+			MenuItem::LoadGame => Self::NewGame,
+			MenuItem::HighScores => Self::LoadGame,
+			MenuItem::Controls => Self::HighScores,
+			MenuItem::Quit => Self::Controls,
 		}
 	}
 }
@@ -43,6 +72,9 @@ impl MenuItem {
 pub struct MenuLoop {
     screen: Screen,
     selected: MenuItem,
+    controls: Controls,
+    /// Shown under the menu until the next key press.
+    message: Option<String>,
 }
 
 impl Loop<'_> for MenuLoop {
@@ -50,16 +82,12 @@ impl Loop<'_> for MenuLoop {
         Self {
             screen,
             selected: MenuItem::NewGame,
+            controls: Controls::default(),
+            message: None,
         }
     }
 
-    fn frame(
-        &mut self,
-        input: &mut termion::AsyncReader,
-        out: &mut RawTerminal<Stdout>,
-    ) -> Result<GameAction, Error> {
-		self.draw(out)?;
-
+    fn frame(&mut self, input: &mut dyn Input) -> Result<GameAction, Error> {
 		let mut events = Vec::new();
 		self.handle_input(input, &mut events);
 
@@ -74,6 +102,9 @@ impl Loop<'_> for MenuLoop {
 					match self.selected {
 						MenuItem::Continue => action = GameAction::Continue,
 						MenuItem::NewGame => action = GameAction::NewGame,
+						MenuItem::LoadGame => action = GameAction::LoadGame,
+						MenuItem::HighScores => action = GameAction::HighScores,
+						MenuItem::Controls => action = GameAction::Controls,
 						MenuItem::Quit => action = GameAction::Quit,
 					},
 				CtrlEvent::Quit => action = GameAction::Quit
@@ -83,99 +114,107 @@ impl Loop<'_> for MenuLoop {
 
 		Ok(action)
     }
+
+    fn draw(&self, out: &mut dyn Renderer) -> Result<(), Error> {
+        let items = MenuItem::ALL
+            .iter()
+            .map(|item| (item.label(), *item == self.selected))
+            .collect::<Vec<_>>();
+
+        out.draw_menu(&self.screen, &items)?;
+
+        match &self.message {
+            Some(message) => {
+                let width = self.screen.size().0.saturating_sub(4);
+                let lines = wrap(message, width);
+                let top = self.screen.size().1.saturating_sub(lines.len() + 1);
+                let lines = lines.into_iter().enumerate().map(|(i, line)| (top + i, line)).collect::<Vec<_>>();
+                out.draw_overlay(&self.screen, &lines)
+            }
+            None => Ok(()),
+        }
+    }
 }
 
 impl MenuLoop {
-	fn handle_input(&mut self, input: &mut termion::AsyncReader, events: &mut Vec<CtrlEvent>) {
-        // TODO Is there a better way to do this?
-        use std::io::Error;
-        use termion::event::Event;
-		
-        let input_events = input.events().collect::<Vec<Result<Event, Error>>>();
-
-        for event in input_events {
-            match event {
-                Ok(event) => match event {
-                    Event::Key(c) => match c {
-                        Key::Char('q') | Key::Ctrl('c') => events.push(CtrlEvent::Quit),
-                        Key::Up | Key::Left => events.push(CtrlEvent::Up),
-                        Key::Down | Key::Right => events.push(CtrlEvent::Down),
-                        Key::Char(' ') | Key::Char('\n') => events.push(CtrlEvent::Select),
-                        _ => (),
-                    },
-                    _ => (),
-                },
-                Err(e) => error!("Stdin error: {}", e.to_string()),
+    pub fn with_controls(mut self, controls: Controls) -> Self {
+        self.controls = controls;
+        self
+    }
+
+    /// Shows a line of text under the menu, e.g. why a game didn't load.
+    pub fn show_message(&mut self, message: String) {
+        self.message = Some(message);
+    }
+
+	fn handle_input(&mut self, input: &mut dyn Input, events: &mut Vec<CtrlEvent>) {
+        for key in input.keys() {
+            self.message = None;
+
+            if let Some(event) = self.controls.menu(key) {
+                events.push(event);
             }
         }
     }
-	
-    fn draw(&self, out: &mut RawTerminal<Stdout>) -> Result<(), Error>{
-		use std::fmt::Write;
-		
-        let margins = self.screen.margins();
-        let margins = (margins.0 as u16, margins.1 as u16);
-        let mut cursor = (margins.0 as u16, margins.1 as u16);
-        let dimensions = self.screen.size();
-		let mut buff = String::with_capacity(self.screen.frame_buffer_size());
-
-        // Top border
-        write!(&mut buff, "{}{}+", termion::clear::All, Goto(cursor.0, cursor.1))?;
-
-        for _ in 0..dimensions.0 {
-            write!(&mut buff, "-")?;
-            cursor.0 += 1;
-        }
+}
 
-        cursor.0 = margins.0 as u16;
-        cursor.1 += 1;
+/// Splits the text into lines no wider than `width`, between words.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
 
-        write!(&mut buff, "+{}", Goto(cursor.0, cursor.1))?;
+    for word in text.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if line.chars().count() + 1 + word.chars().count() <= width => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => lines.push(word.to_string()),
+        }
+    }
 
-        // Write the menu items
-        // TODO This is stupid. Please redo!
+    lines
+}
 
-		let width = self.screen.size().0;
-		let bottom = self.screen.size().1;
-		let new_game_y = bottom / 2;
-		let continue_y = new_game_y - 1;
-		let quit_y = new_game_y + 1;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{Key, ScriptedInput};
+    use crate::render::HeadlessRenderer;
+    use crate::utils::Coord;
+
+    fn draw(menu: &MenuLoop) -> Vec<String> {
+        let mut out = HeadlessRenderer::default();
+        menu.draw(&mut out).unwrap();
+        out.frame().to_vec()
+    }
 
-		for y in 1..bottom {
-			write!(&mut buff, "|")?;
+    #[test]
+    fn draws_the_menu_in_a_border() {
+        let menu = MenuLoop::init(Screen::new(Coord(45, 15)));
+        let frame = draw(&menu);
 
-			if y == continue_y {
-				write!(&mut buff, "\t{} Continue", if self.selected == MenuItem::Continue {">"} else {" "})?;
-			} else if y == new_game_y {
-				write!(&mut buff, "\t{} New Game", if self.selected == MenuItem::NewGame {">"} else {" "})?;
-			} else if y == quit_y {
-				write!(&mut buff, "\t{} Quit", if self.selected == MenuItem::Quit {">"} else {" "})?;
-			}
+        assert_eq!(frame.len(), 17);
+        assert_eq!(frame[0], format!("+{}+", "-".repeat(45)));
+        assert_eq!(frame[16], frame[0]);
+        assert!(frame[1..16].iter().all(|row| row.starts_with('|') && row.ends_with('|')));
+    }
 
-			write!(&mut buff, "{}|{}", Goto(margins.0 + 1 + width as u16, margins.1 + y as u16), Goto(margins.0, margins.1 + y as u16 + 1))?;
-		}
-		
-        
-        // Contents
-        // for y in 6..dimensions.1 {
-        //     write!(&mut buff, "|{}|{}", Goto(margins.0 + dimensions.0, margins.1 + y as u16 + 1), Goto(margins.0, margins.1 + y as u16 + 1));
-        //     write!(&mut buff,"|{}",
-
-        //     );
-        // }
-
-        // Bottom border
-        write!(&mut buff, "+")?;
-        for _ in 0..dimensions.0 {
-            write!(&mut buff, "-")?;
-        }
-        write!(&mut buff, "+")?;
+    #[test]
+    fn moves_the_cursor_and_selects() {
+        let mut menu = MenuLoop::init(Screen::new(Coord(45, 15)));
+        let mut input = ScriptedInput::new(vec![vec![], vec![Key::Down], vec![Key::Enter]]);
 
-        write!(&mut buff, "{}", Goto(1, 1))?;
+        // The items start in the middle, on row 6, and inside the border
+        assert!(matches!(menu.frame(&mut input).unwrap(), GameAction::Menu));
+        let frame = draw(&menu);
+        assert!(frame[7].contains("  Continue "));
+        assert!(frame[8].contains("> New Game "));
 
-		write!(out, "{}", buff)?;
-        out.flush().unwrap();
+        assert!(matches!(menu.frame(&mut input).unwrap(), GameAction::Menu));
+        let frame = draw(&menu);
+        assert!(frame[8].contains("  New Game "));
+        assert!(frame[9].contains("> Load Game"));
 
-		Ok(())
+        assert!(matches!(menu.frame(&mut input).unwrap(), GameAction::LoadGame));
     }
 }
@@ -1,112 +1,419 @@
+use crate::controls::Controls;
+use crate::game::controls_loop::ControlsLoop;
 use crate::game::game_loop::GameLoop;
+use crate::game::game_over_loop::GameOverLoop;
+use crate::game::high_scores_loop::HighScoresLoop;
 use crate::game::menu_loop::MenuLoop;
+use crate::game::name_entry_loop::NameEntryLoop;
+use crate::game::pause_loop::PauseLoop;
+use crate::high_scores::HighScores;
+use crate::input::Input;
+use crate::render::{self, Renderer};
+use crate::replay::Replay;
+use crate::save::SavedGame;
+use crate::level::Level;
 use crate::utils::*;
-use std::thread;
+use crate::clock::{Clock, Timing};
+use std::fs;
+use std::path::PathBuf;
 use std::time;
 use failure::Error;
-use std::io::{Stdout, Write};
-use termion::raw::RawTerminal;
 
-mod game_loop;
-mod menu_loop;
+mod controls_loop;
+pub mod game_loop;
+mod game_over_loop;
+mod high_scores_loop;
+pub mod menu_loop;
+mod name_entry_loop;
+mod pause_loop;
+pub(crate) mod swarm;
 
 pub trait Loop<'a> {
     fn init(screen: Screen) -> Self;
-    fn frame(&mut self, input: &mut termion::AsyncReader, out: &mut RawTerminal<Stdout>) -> Result<GameAction, Error>;
+    /// Runs one tick, reading whatever keys were pressed since the last one.
+    fn frame(&mut self, input: &mut dyn Input) -> Result<GameAction, Error>;
+    /// Draws the current state. Called at the render rate, which may not
+    /// line up with the ticks.
+    fn draw(&self, out: &mut dyn Renderer) -> Result<(), Error>;
 }
 
 pub enum GameState {
     Menu,
     Running,
     Done,
+    NameEntry,
+    HighScores,
+    Paused,
+    Controls,
 }
 
 pub enum GameAction {
     NewGame,
 	EndGame,
+    GameOver,
+    EnterName,
+    HighScores,
     Continue,
+    Pause,
+    Controls,
+    SaveGame,
+    LoadGame,
     Menu,
     Quit,
 }
 
 pub struct Game<'a> {
     // IO stuff
-    out: &'a mut RawTerminal<Stdout>,
-    input: &'a mut termion::AsyncReader,
+    out: &'a mut dyn Renderer,
+    input: &'a mut dyn Input,
 
     // Game state info
     screen: Screen,
+    levels: Vec<Level>,
+    /// Fixed seed for every new game, otherwise each one gets a random seed.
+    seed: Option<u64>,
+    last_score: u32,
+    last_seed: u64,
+    timing: Timing,
+    /// Where to save a recording of each game.
+    record: Option<PathBuf>,
+    /// Just watching one game, played back or by the bot. Stop once it's over.
+    single_game: bool,
+    /// Let the bot play every game.
+    bot: bool,
+    controls: Controls,
 
     // Loops for game states
     game_loop: GameLoop,
     menu_loop: MenuLoop,
+    game_over_loop: GameOverLoop,
+    name_entry_loop: NameEntryLoop,
+    high_scores_loop: HighScoresLoop,
+    pause_loop: PauseLoop,
+    controls_loop: ControlsLoop,
     state: GameState,
 }
 
 impl<'a> Game<'a> {
-    pub fn new(input: &'a mut termion::AsyncReader, out: &'a mut RawTerminal<Stdout>) -> Self {
-        let screen_size = Coord(45, 15);
-        let term_size = termion::terminal_size();
-
-        let margins = if let Ok((w, h)) = term_size {
-			let x = (w as usize - screen_size.0) / 2;
-			let y = (h as usize - screen_size.0) / 2;
-            Coord(x, y)
-        } else {
-            Coord(0, 0)
-        };
-
-        let screen = Screen::new(margins, screen_size);
-        let game_loop = GameLoop::init(screen.clone());
-        let menu_loop = MenuLoop::init(screen.clone());
+    /// A game on the given levels, starting with the first. Fails if there are none.
+    pub fn new(input: &'a mut dyn Input, out: &'a mut dyn Renderer, levels: Vec<Level>) -> Result<Self, Error> {
+        let first = levels.first().ok_or_else(|| format_err!("There are no levels to play"))?;
+        let screen = Screen::new(first.size());
+        let controls = Controls::load_or_default();
+        let mut game_loop = GameLoop::with_levels(screen, levels.clone(), rand::random(), Default::default());
+        game_loop.set_controls(controls.clone());
+        let menu_loop = MenuLoop::init(screen).with_controls(controls.clone());
+        let game_over_loop = GameOverLoop::init(screen);
+        let name_entry_loop = NameEntryLoop::init(screen);
+        let high_scores_loop = HighScoresLoop::init(screen);
+        let pause_loop = PauseLoop::init(screen);
+        let controls_loop = ControlsLoop::new(screen, controls.clone());
         let state = GameState::Menu;
 
-        Self {
+        Ok(Self {
             game_loop,
             menu_loop,
+            game_over_loop,
+            name_entry_loop,
+            high_scores_loop,
+            pause_loop,
+            controls_loop,
             state,
             screen,
+            levels,
+            seed: None,
+            last_score: 0,
+            last_seed: 0,
+            timing: Timing::default(),
+            record: None,
+            single_game: false,
+            bot: false,
+            controls,
             out,
             input,
+        })
+    }
+
+    /// Plays every game with the given seed, instead of a random one.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self.game_loop = self.new_game_loop();
+        self
+    }
+
+    /// Sets how fast the game ticks and draws. Comes before `with_replay`,
+    /// which plays at the rate it was recorded at.
+    pub fn with_timing(mut self, timing: Timing) -> Self {
+        self.timing = timing;
+        self.game_loop = self.new_game_loop();
+        self
+    }
+
+    /// Saves a recording of each game to the given file, replacing the
+    /// previous one.
+    pub fn with_recording(mut self, path: PathBuf) -> Self {
+        self.record = Some(path);
+        self
+    }
+
+    /// Skips the menu and plays back a recorded game, then quits.
+    pub fn with_replay(mut self, replay: Replay) -> Result<Self, Error> {
+        self.timing.tick_rate = replay.rate();
+        self.game_loop = GameLoop::with_replay(self.screen, self.levels.clone(), replay)?;
+        self.game_loop.set_controls(self.controls.clone());
+        self.state = GameState::Running;
+        self.single_game = true;
+        Ok(self)
+    }
+
+    /// Skips the menu and lets the bot play a game, then quits.
+    pub fn with_bot(mut self) -> Self {
+        self.bot = true;
+        self.game_loop = self.new_game_loop();
+        self.state = GameState::Running;
+        self.single_game = true;
+        self
+    }
+
+    fn save_recording(&self) {
+        if let Some(path) = &self.record {
+            if let Err(e) = self.game_loop.recording().save(path) {
+                error!("Failed to save recording to {}: {}", path.display(), e);
+            }
         }
     }
 
+    fn new_game_loop(&self) -> GameLoop {
+        let seed = self.seed.unwrap_or_else(rand::random);
+        let mut game_loop = GameLoop::with_levels(self.screen, self.levels.clone(), seed, self.timing.tick_rate);
+        game_loop.set_controls(self.controls.clone());
+        game_loop.set_autopilot(self.bot);
+        game_loop
+    }
+
+    /// Saves the game in progress, to pick up again with `load_game`.
+    fn save_game(&self) -> Result<(), Error> {
+        self.game_loop.to_save().save()
+    }
+
+    /// The saved game, on whichever of the levels it needs.
+    fn load_game(&self) -> Result<GameLoop, Error> {
+        let path = SavedGame::path().ok_or_else(|| format_err!("No data directory available"))?;
+
+        if !path.exists() {
+            return Err(format_err!("There's no saved game"));
+        }
+
+        let saved = SavedGame::load(&path)?;
+
+        // The ones in play, then the usual ones, in case it was saved on different levels
+        let available = self.levels.iter().cloned().chain(Level::load_or_default()).collect::<Vec<_>>();
+
+        let mut game_loop = GameLoop::from_save(self.screen, &available, saved)?;
+        game_loop.set_controls(self.controls.clone());
+
+        // A save can only be picked up once, or it'd undo whatever happens next
+        fs::remove_file(&path)?;
+
+        Ok(game_loop)
+    }
+
+    /// Hands the bindings from the controls screen to everything reading keys.
+    fn apply_controls(&mut self) {
+        self.controls = self.controls_loop.controls().clone();
+        self.game_loop.set_controls(self.controls.clone());
+        self.pause_loop.set_controls(self.controls.clone());
+        self.menu_loop = MenuLoop::init(self.screen).with_controls(self.controls.clone());
+    }
+
     pub fn run(&mut self) {
-		loop {
+		let mut clock = Clock::new(self.timing);
+
+		'running: loop {
+			let ticks = clock.advance();
+
 			// Timer!
 			let now = time::Instant::now();
 
-			let action  = match self.state {
-				GameState::Menu => self.menu_loop.frame(self.input, self.out),
-				GameState::Running => self.game_loop.frame(self.input, self.out),
-				GameState::Done => self.menu_loop.frame(self.input, self.out)
-			}.expect("Encountered error: ");
-			
-			match action {
-				GameAction::Continue => {
-					self.state = GameState::Running;
-				},
-				GameAction::NewGame => {
-					self.game_loop = GameLoop::init(self.screen.clone());
-					self.state = GameState::Running;
-				},
-				GameAction::EndGame => {
-					self.game_loop = GameLoop::init(self.screen.clone());
-					self.state = GameState::Menu;
-				},
-				GameAction::Menu => self.state = GameState::Menu,
-				GameAction::Quit => break,
+			for _ in 0..ticks {
+				if !self.tick() {
+					break 'running;
+				}
+			}
+
+			// Hold the game while it can't be seen, the player can resume once it fits again
+			if !self.fits() {
+				if let GameState::Running = self.state {
+					self.pause();
+				}
+			}
+
+			if clock.should_render() {
+				self.draw();
+
+				// TODO support separate DEBUG mode?
+				self.out.draw_status(&format!("{:?}", now.elapsed())).unwrap();
+				self.out.end_frame(clock.time()).unwrap();
 			}
+		}
+
+		// Show how things ended up, even if it wasn't time for a frame
+		self.draw();
+		self.out.end_frame(clock.time()).unwrap();
 
-			// TODO support separate DEBUG mode?
-			write!(self.out, "{}{:?}", termion::cursor::Goto(1, 1), now.elapsed());
-			self.out.flush().unwrap();
+		// Keep the game that was still going, if any
+		if self.game_loop.recording().length() > 0 {
+			self.save_recording();
+		}
+
+        self.out.finish().unwrap();
+    }
+
+    /// Runs the current screen for one tick. Returns false once it's time to stop.
+    fn tick(&mut self) -> bool {
+		let action  = match self.state {
+			GameState::Menu => self.menu_loop.frame(self.input),
+			GameState::Running => self.game_loop.frame(self.input),
+			GameState::Done => self.game_over_loop.frame(self.input),
+			GameState::NameEntry => self.name_entry_loop.frame(self.input),
+			GameState::HighScores => self.high_scores_loop.frame(self.input),
+			GameState::Paused => self.pause_loop.frame(self.input),
+			GameState::Controls => self.controls_loop.frame(self.input),
+		}.expect("Encountered error: ");
+
+		// Pausing is fine, anything else means the replay is over
+		if self.single_game && !matches!(action, GameAction::Continue | GameAction::Pause | GameAction::Controls) {
+			return false;
+		}
+
+		if matches!(self.state, GameState::Controls) && !matches!(action, GameAction::Controls) {
+			self.apply_controls();
+		}
+
+		match action {
+			GameAction::Continue => {
+				self.state = GameState::Running;
+			},
+			GameAction::NewGame => {
+				self.game_loop = self.new_game_loop();
+				self.state = GameState::Running;
+			},
+			GameAction::EndGame => {
+				if let GameState::Running = self.state {
+					self.last_score = self.game_loop.score().total();
+					self.last_seed = self.game_loop.seed();
+					self.save_recording();
+					self.game_loop = self.new_game_loop();
+				}
+
+				if HighScores::load_or_default().qualifies(self.last_score) {
+					self.name_entry_loop = NameEntryLoop::init(self.screen).with_score(self.last_score, self.last_seed);
+					self.state = GameState::NameEntry;
+				} else {
+					self.state = GameState::Menu;
+				}
+			},
+			GameAction::GameOver => {
+				// Only reset things on the way in, the game over screen keeps returning GameOver
+				if let GameState::Running = self.state {
+					self.last_score = self.game_loop.score().total();
+					self.last_seed = self.game_loop.seed();
+					self.save_recording();
+					self.game_loop = self.new_game_loop();
+					self.game_over_loop = GameOverLoop::init(self.screen).with_tick_rate(self.timing.tick_rate);
+				}
+				self.state = GameState::Done;
+			},
+			GameAction::Pause => {
+				// Start the menu afresh when coming from the game, not back from the controls
+				if !matches!(self.state, GameState::Paused | GameState::Controls) {
+					self.pause();
+				}
+				self.state = GameState::Paused;
+			},
+			GameAction::Controls => {
+				if !matches!(self.state, GameState::Controls) {
+					let controls_loop = ControlsLoop::new(self.screen, self.controls.clone());
+					self.controls_loop = match self.state {
+						GameState::Paused => controls_loop.in_game(),
+						_ => controls_loop,
+					};
+				}
+				self.state = GameState::Controls;
+			},
+			GameAction::EnterName => self.state = GameState::NameEntry,
+			GameAction::HighScores => {
+				// Reload on the way in, to pick up any new entry
+				if !matches!(self.state, GameState::HighScores) {
+					self.high_scores_loop = HighScoresLoop::init(self.screen);
+				}
+				self.state = GameState::HighScores;
+			},
+			GameAction::SaveGame => match self.save_game() {
+				Ok(()) => return false,
+				Err(e) => {
+					error!("Failed to save the game: {}", e);
+					self.pause_loop.show_message("Couldn't save the game".to_string());
+				}
+			},
+			GameAction::LoadGame => match self.load_game() {
+				Ok(game_loop) => {
+					self.game_loop = game_loop;
+					self.state = GameState::Running;
+				}
+				Err(e) => {
+					self.menu_loop.show_message(format!("Can't load: {}", e));
+				}
+			},
+			GameAction::Menu => self.state = GameState::Menu,
+			GameAction::Quit => return false,
+		}
+
+		true
+    }
+
+    /// Stops the game where it is, and brings up the pause menu.
+    fn pause(&mut self) {
+        self.pause_loop = PauseLoop::init(*self.game_loop.screen()).with_controls(self.controls.clone());
+        self.state = GameState::Paused;
+    }
+
+    /// The terminal size the current screen needs. Games can be bigger or
+    /// smaller than the menus, if the levels are.
+    fn required_size(&self) -> Coord {
+        let menus = render::required_size(&self.screen);
+        let game = render::required_size(self.game_loop.screen());
+        Coord(std::cmp::max(menus.0, game.0), std::cmp::max(menus.1, game.1))
+    }
+
+    /// Whether everything fits in the terminal, as it is right now.
+    fn fits(&self) -> bool {
+        let need = self.required_size();
+
+        match self.out.terminal_size() {
+            Some(size) => size.0 >= need.0 && size.1 >= need.1,
+            None => true,
+        }
+    }
 
-			// Wait
-			thread::sleep(time::Duration::from_millis(30) - now.elapsed());
+    fn draw(&mut self) {
+		if !self.fits() {
+			let need = self.required_size();
+			self.out.draw_too_small(need).expect("Encountered error: ");
+			return;
 		}
-		
 
-        write!(self.out, "{}", termion::cursor::Show).unwrap();
+		match self.state {
+			GameState::Menu => self.menu_loop.draw(self.out),
+			GameState::Running => self.game_loop.draw(self.out),
+			GameState::Done => self.game_over_loop.draw(self.out),
+			GameState::NameEntry => self.name_entry_loop.draw(self.out),
+			GameState::HighScores => self.high_scores_loop.draw(self.out),
+			GameState::Paused => self
+				.game_loop
+				.draw(self.out)
+				.and_then(|_| self.pause_loop.draw(self.out)),
+			GameState::Controls => self.controls_loop.draw(self.out),
+		}.expect("Encountered error: ");
     }
 }
@@ -0,0 +1,111 @@
+use crate::game::GameAction;
+use crate::game::Loop;
+use crate::high_scores::{HighScores, NAME_LENGTH};
+use crate::input::{Input, Key};
+use crate::render::Renderer;
+use crate::utils::Screen;
+use failure::Error;
+
+pub enum CtrlEvent {
+    Type(char),
+    Erase,
+    Confirm,
+    Skip,
+}
+
+pub struct NameEntryLoop {
+    screen: Screen,
+    scores: HighScores,
+    score: u32,
+    seed: u64,
+    name: String,
+}
+
+impl Loop<'_> for NameEntryLoop {
+    fn init(screen: Screen) -> Self {
+        Self {
+            screen,
+            scores: HighScores::load_or_default(),
+            score: 0,
+            seed: 0,
+            name: String::with_capacity(NAME_LENGTH),
+        }
+    }
+
+    fn frame(&mut self, input: &mut dyn Input) -> Result<GameAction, Error> {
+        let mut events = Vec::new();
+        self.handle_input(input, &mut events);
+
+        let mut action = GameAction::EnterName;
+
+        for event in events {
+            match event {
+                CtrlEvent::Type(c) => {
+                    if self.name.len() < NAME_LENGTH {
+                        self.name.push(c);
+                    }
+                }
+                CtrlEvent::Erase => {
+                    self.name.pop();
+                }
+                CtrlEvent::Confirm => {
+                    if !self.name.is_empty() {
+                        self.scores.insert(&self.name, self.score, self.seed);
+
+                        if let Err(e) = self.scores.save() {
+                            error!("Failed to save high scores: {}", e);
+                        }
+
+                        action = GameAction::HighScores;
+                        break;
+                    }
+                }
+                CtrlEvent::Skip => {
+                    action = GameAction::Menu;
+                    break;
+                }
+            }
+        }
+
+        Ok(action)
+    }
+
+    fn draw(&self, out: &mut dyn Renderer) -> Result<(), Error> {
+        let title_y = (self.screen.size().1 / 2).saturating_sub(2);
+
+        // Pad the name out with underscores so it's obvious how long it can be.
+        let name = format!("{:_<width$}", self.name, width = NAME_LENGTH);
+
+        let lines = vec![
+            (title_y, "NEW HIGH SCORE".to_string()),
+            (title_y + 1, self.score.to_string()),
+            (title_y + 3, format!("Name: {}", name)),
+            (title_y + 5, "Enter to save, Esc to skip".to_string()),
+        ];
+
+        out.draw_text(&self.screen, &lines)
+    }
+}
+
+impl NameEntryLoop {
+    /// The score to enter a name for, and the seed of the game it came from.
+    pub fn with_score(mut self, score: u32, seed: u64) -> Self {
+        self.score = score;
+        self.seed = seed;
+        self
+    }
+
+    fn handle_input(&mut self, input: &mut dyn Input, events: &mut Vec<CtrlEvent>) {
+        for key in input.keys() {
+            match key {
+                Key::Enter => events.push(CtrlEvent::Confirm),
+                Key::Char(c) if c.is_ascii_alphanumeric() => {
+                    events.push(CtrlEvent::Type(c.to_ascii_uppercase()))
+                }
+                Key::Backspace | Key::Delete => events.push(CtrlEvent::Erase),
+                Key::Esc | Key::Ctrl('c') => events.push(CtrlEvent::Skip),
+                _ => (),
+            }
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use crate::controls::Controls;
+use crate::game::game_loop::CtrlEvent as GameEvent;
+use crate::game::menu_loop::CtrlEvent as MenuEvent;
+use crate::game::GameAction;
+use crate::game::Loop;
+use crate::input::{Input, Key};
+use crate::render::Renderer;
+use crate::utils::Screen;
+use failure::Error;
+
+pub enum CtrlEvent {
+    Up,
+    Down,
+    Select,
+    Resume,
+    Quit,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum PauseItem {
+    Resume,
+    Restart,
+    Controls,
+    SaveAndQuit,
+    QuitToMenu,
+}
+
+impl PauseItem {
+    /// Every item, in the order they're shown.
+    pub const ALL: [PauseItem; 5] = [
+        PauseItem::Resume,
+        PauseItem::Restart,
+        PauseItem::Controls,
+        PauseItem::SaveAndQuit,
+        PauseItem::QuitToMenu,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PauseItem::Resume => "Resume",
+            PauseItem::Restart => "Restart",
+            PauseItem::Controls => "Controls",
+            PauseItem::SaveAndQuit => "Save & Quit",
+            PauseItem::QuitToMenu => "Quit to Menu",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            PauseItem::Resume => Self::Restart,
+            PauseItem::Restart => Self::Controls,
+            PauseItem::Controls => Self::SaveAndQuit,
+            PauseItem::SaveAndQuit => Self::QuitToMenu,
+            PauseItem::QuitToMenu => Self::Resume,
+        }
+    }
+
+    pub fn previous(&self) -> Self {
+        match self {
+            PauseItem::Resume => Self::QuitToMenu,
+            PauseItem::Restart => Self::Resume,
+            PauseItem::Controls => Self::Restart,
+            PauseItem::SaveAndQuit => Self::Controls,
+            PauseItem::QuitToMenu => Self::SaveAndQuit,
+        }
+    }
+}
+
+/// The pause menu, drawn over the frozen game. The game itself is left
+/// alone, so resuming picks up exactly where it stopped.
+pub struct PauseLoop {
+    screen: Screen,
+    selected: PauseItem,
+    controls: Controls,
+    /// Why the last save failed, if it did.
+    message: Option<String>,
+}
+
+impl Loop<'_> for PauseLoop {
+    fn init(screen: Screen) -> Self {
+        Self {
+            screen,
+            selected: PauseItem::Resume,
+            controls: Controls::default(),
+            message: None,
+        }
+    }
+
+    fn frame(&mut self, input: &mut dyn Input) -> Result<GameAction, Error> {
+        let mut events = Vec::new();
+        self.handle_input(input, &mut events);
+
+        let mut action = GameAction::Pause;
+
+        for event in events {
+            match event {
+                CtrlEvent::Down => self.selected = self.selected.next(),
+                CtrlEvent::Up => self.selected = self.selected.previous(),
+                CtrlEvent::Select => {
+                    action = match self.selected {
+                        PauseItem::Resume => GameAction::Continue,
+                        PauseItem::Restart => GameAction::NewGame,
+                        PauseItem::Controls => GameAction::Controls,
+                        PauseItem::SaveAndQuit => GameAction::SaveGame,
+                        PauseItem::QuitToMenu => GameAction::Menu,
+                    }
+                }
+                CtrlEvent::Resume => action = GameAction::Continue,
+                CtrlEvent::Quit => action = GameAction::Menu,
+            }
+        }
+
+        Ok(action)
+    }
+
+    /// Only the menu itself, the game should be drawn underneath first.
+    fn draw(&self, out: &mut dyn Renderer) -> Result<(), Error> {
+        let top = (self.screen.size().1 / 2).saturating_sub(3);
+        let mut lines = vec![(top, "PAUSED".to_string()), (top + 1, String::new())];
+
+        // Line the items up on the left, like the main menu
+        let width = PauseItem::ALL.iter().map(|item| item.label().len()).max().unwrap_or(0);
+
+        for (i, item) in PauseItem::ALL.iter().enumerate() {
+            let cursor = if *item == self.selected { ">" } else { " " };
+            let text = format!("{} {:<width$}", cursor, item.label(), width = width);
+            lines.push((top + 2 + i, text));
+        }
+
+        if let Some(message) = &self.message {
+            lines.push((top + 3 + PauseItem::ALL.len(), message.clone()));
+        }
+
+        out.draw_overlay(&self.screen, &lines)
+    }
+}
+
+impl PauseLoop {
+    pub fn with_controls(mut self, controls: Controls) -> Self {
+        self.controls = controls;
+        self
+    }
+
+    /// Reads the keys with new bindings, keeping the menu where it is.
+    pub fn set_controls(&mut self, controls: Controls) {
+        self.controls = controls;
+    }
+
+    /// Shows a line of text under the menu, until the next key press.
+    pub fn show_message(&mut self, message: String) {
+        self.message = Some(message);
+    }
+
+    fn handle_input(&mut self, input: &mut dyn Input, events: &mut Vec<CtrlEvent>) {
+        for key in input.keys() {
+            self.message = None;
+
+            // Whatever paused the game unpauses it, and Esc always backs out
+            if key == Key::Esc || self.controls.game(key) == Some(GameEvent::Pause) {
+                events.push(CtrlEvent::Resume);
+                continue;
+            }
+
+            match self.controls.menu(key) {
+                Some(MenuEvent::Up) => events.push(CtrlEvent::Up),
+                Some(MenuEvent::Down) => events.push(CtrlEvent::Down),
+                Some(MenuEvent::Select) => events.push(CtrlEvent::Select),
+                Some(MenuEvent::Quit) => events.push(CtrlEvent::Quit),
+                None => (),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controls::Preset;
+    use crate::game::game_loop::GameLoop;
+    use crate::input::{Key, ScriptedInput};
+    use crate::utils::Coord;
+    use crate::level::Level;
+    use crate::render::HeadlessRenderer;
+
+    #[test]
+    fn draws_over_the_middle_of_the_game() {
+        let mut game = GameLoop::new(Level::defaults(), 1).unwrap();
+        let mut input = ScriptedInput::new(Vec::new());
+        while game.is_between_waves() {
+            game.frame(&mut input).unwrap();
+        }
+
+        let mut out = HeadlessRenderer::default();
+        game.draw(&mut out).unwrap();
+        let under = out.frame().to_vec();
+        PauseLoop::init(*game.screen()).draw(&mut out).unwrap();
+        let frame = out.frame();
+
+        // A panel as wide as the longest item, plus a space either side,
+        // starting on row 4 of the 45x15 field, inside the border
+        let (left, width) = (1 + 14, 16);
+        assert_eq!(&frame[5][left..left + width], "     PAUSED     ");
+        assert_eq!(&frame[6][left..left + width], " ".repeat(width));
+        assert_eq!(&frame[7][left..left + width], " > Resume       ");
+        assert_eq!(&frame[11][left..left + width], "   Quit to Menu ");
+
+        // The game shows around it, and the border and HUD are untouched
+        for (y, (row, before)) in frame.iter().zip(under.iter()).enumerate() {
+            if (5..12).contains(&y) {
+                assert_eq!(row[..left], before[..left]);
+                assert_eq!(row[left + width..], before[left + width..]);
+            } else {
+                assert_eq!(row, before);
+            }
+        }
+    }
+
+    #[test]
+    fn reads_keys_with_new_controls() {
+        let mut pause = PauseLoop::init(Screen::new(Coord(45, 15)));
+        pause.set_controls(Controls::preset(Preset::Vi));
+        let mut input = ScriptedInput::new(vec![vec![Key::Char('j'), Key::Enter]]);
+
+        assert!(matches!(pause.frame(&mut input).unwrap(), GameAction::NewGame));
+    }
+}
@@ -0,0 +1,138 @@
+use crate::entities::{Entity, Invader};
+use crate::utils::Dir;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+/// Moves the invaders as one block, like the arcade original. The whole
+/// swarm marches sideways until any member reaches the edge, then they all
+/// drop a row and turn around together. The fewer are left, the faster
+/// they go.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "SwarmState")]
+pub struct Swarm {
+    pub direction: Dir,
+    /// Ticks between steps with the full swarm alive.
+    pub step_interval: u32,
+    /// How many invaders the swarm started with.
+    size: usize,
+    timer: u32,
+}
+
+/// A swarm as it's saved, checked before it becomes a `Swarm`.
+#[derive(Deserialize)]
+struct SwarmState {
+    direction: Dir,
+    step_interval: u32,
+    size: usize,
+    timer: u32,
+}
+
+impl TryFrom<SwarmState> for Swarm {
+    type Error = String;
+
+    fn try_from(state: SwarmState) -> Result<Self, String> {
+        if state.step_interval == 0 || state.size == 0 {
+            return Err("the swarm's step interval and size must be at least 1".to_string());
+        }
+
+        if state.direction != Dir::Left && state.direction != Dir::Right {
+            return Err("the swarm can only march left or right".to_string());
+        }
+
+        let mut rv = Self::new(state.step_interval, state.size);
+        rv.direction = state.direction;
+        rv.timer = state.timer;
+        Ok(rv)
+    }
+}
+
+impl Swarm {
+    pub fn new(step_interval: u32, size: usize) -> Self {
+        Self {
+            direction: Dir::Right,
+            step_interval: std::cmp::max(step_interval, 1),
+            size: std::cmp::max(size, 1),
+            timer: 0,
+        }
+    }
+
+    /// Ticks until the next step, scaled down by how much of the swarm is left.
+    pub fn current_interval(&self, alive: usize) -> u32 {
+        let alive = std::cmp::min(alive, self.size);
+        let interval = (self.step_interval as usize * alive).div_ceil(self.size);
+        std::cmp::max(interval, 1) as u32
+    }
+
+    /// Counts down to the next step, and takes it when it's time.
+    /// Invaders that are out diving are left alone.
+    pub fn update(&mut self, invaders: &mut [Invader], width: usize) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+
+        let alive = invaders.iter().filter(|invader| !invader.diving).count();
+        self.timer = self.current_interval(alive) - 1;
+
+        let mut members = invaders.iter().filter(|invader| !invader.diving);
+
+        // Whichever edge of a sprite comes first, however wide it is
+        let at_edge = members.any(|invader| {
+            let (left, right) = invader.sprite().reach();
+            match self.direction {
+                Dir::Left => invader.position.0 <= left,
+                _ => invader.position.0 + right >= width - 1,
+            }
+        });
+
+        for invader in invaders.iter_mut().filter(|invader| !invader.diving) {
+            invader.steps = invader.steps.wrapping_add(1);
+
+            if at_edge {
+                invader.position.1 += 1;
+            } else if self.direction == Dir::Left {
+                invader.position.0 -= 1;
+            } else {
+                invader.position.0 += 1;
+            }
+        }
+
+        if at_edge {
+            self.direction = match self.direction {
+                Dir::Left => Dir::Right,
+                _ => Dir::Left,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::InvaderKind;
+    use crate::utils::Coord;
+
+    #[test]
+    fn turns_when_a_sprite_reaches_the_edge() {
+        let mut swarm = Swarm::new(1, 2);
+        let mut invaders = vec![
+            Invader::new(InvaderKind::Squid, Coord(1, 0)),
+            Invader::new(InvaderKind::Squid, Coord(8, 0)),
+        ];
+
+        swarm.update(&mut invaders, 10);
+        assert_eq!(invaders[1].position, Coord(9, 0));
+
+        // Down a row at the right edge, then back the other way
+        swarm.update(&mut invaders, 10);
+        assert_eq!(invaders[1].position, Coord(9, 1));
+        assert!(swarm.direction == Dir::Left);
+
+        swarm.update(&mut invaders, 10);
+        swarm.update(&mut invaders, 10);
+        assert_eq!(invaders[0].position, Coord(0, 1));
+        swarm.update(&mut invaders, 10);
+        assert_eq!(invaders[0].position, Coord(0, 2));
+        assert!(swarm.direction == Dir::Right);
+    }
+}
@@ -0,0 +1,271 @@
+use failure::Error;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// First line of the scores file. Bump the number if the format changes.
+const HEADER: &str = "space-invaders high scores v2";
+/// Older files without a seed column are still read.
+const HEADER_V1: &str = "space-invaders high scores v1";
+pub const TABLE_SIZE: usize = 10;
+pub const NAME_LENGTH: usize = 3;
+
+pub struct HighScore {
+    pub name: String,
+    pub score: u32,
+    /// Seed of the game the score came from, so it can be replayed.
+    /// Missing for scores carried over from v1 files.
+    pub seed: Option<u64>,
+}
+
+/// The top `TABLE_SIZE` scores, highest first.
+#[derive(Default)]
+pub struct HighScores {
+    entries: Vec<HighScore>,
+}
+
+impl HighScores {
+    /// Where the table is stored, under the XDG data directory.
+    pub fn path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("space-invaders").join("high_scores"))
+    }
+
+    /// Loads the table, falling back to an empty one if the file is
+    /// missing or can't be read.
+    pub fn load_or_default() -> Self {
+        match Self::path() {
+            Some(path) => Self::load_or_empty(&path),
+            None => Self::default(),
+        }
+    }
+
+    /// The table at `path`, or an empty one if it's missing or broken.
+    pub fn load_or_empty(path: &Path) -> Self {
+        if !path.exists() {
+            return Self::default();
+        }
+
+        Self::load(path).unwrap_or_else(|e| {
+            warn!("Ignoring high score file {}: {}", path.display(), e);
+            Self::default()
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(source: &str) -> Result<Self, Error> {
+        let mut lines = source.lines();
+
+        let has_seeds = match lines.next() {
+            Some(header) => match header.trim() {
+                HEADER => true,
+                HEADER_V1 => false,
+                _ => return Err(format_err!("Unrecognised header: {:?}", header)),
+            },
+            None => return Err(format_err!("File is empty")),
+        };
+
+        let mut entries = Vec::with_capacity(TABLE_SIZE);
+
+        for line in lines {
+            let line = line.trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut fields = line.split_whitespace();
+            let entry = match (fields.next(), fields.next(), fields.next(), fields.next()) {
+                (Some(name), Some(score), Some(seed), None) if has_seeds => HighScore {
+                    name: name.to_string(),
+                    score: score.parse()?,
+                    seed: match seed {
+                        "-" => None,
+                        seed => Some(seed.parse()?),
+                    },
+                },
+                (Some(name), Some(score), None, None) if !has_seeds => HighScore {
+                    name: name.to_string(),
+                    score: score.parse()?,
+                    seed: None,
+                },
+                _ => return Err(format_err!("Malformed entry: {:?}", line)),
+            };
+
+            entries.push(entry);
+        }
+
+        let mut scores = Self { entries };
+        scores.entries.sort_by_key(|entry| std::cmp::Reverse(entry.score));
+        scores.entries.truncate(TABLE_SIZE);
+
+        Ok(scores)
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let path = Self::path().ok_or_else(|| format_err!("No data directory available"))?;
+        self.save_to(&path)
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<(), Error> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        // Write to a temporary file first, so a crash can't leave a half-written table.
+        let tmp = path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp)?;
+
+        writeln!(file, "{}", HEADER)?;
+        for entry in &self.entries {
+            match entry.seed {
+                Some(seed) => writeln!(file, "{} {} {}", entry.name, entry.score, seed)?,
+                None => writeln!(file, "{} {} -", entry.name, entry.score)?,
+            }
+        }
+
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[HighScore] {
+        &self.entries
+    }
+
+    /// Whether the score is good enough to make it onto the table.
+    pub fn qualifies(&self, score: u32) -> bool {
+        score > 0
+            && (self.entries.len() < TABLE_SIZE
+                || self.entries.iter().any(|entry| entry.score < score))
+    }
+
+    /// Adds a score to the table, returning its rank if it made the cut.
+    pub fn insert(&mut self, name: &str, score: u32, seed: u64) -> Option<usize> {
+        if !self.qualifies(score) {
+            return None;
+        }
+
+        // Ties go to whoever got there first
+        let rank = self
+            .entries
+            .iter()
+            .position(|entry| entry.score < score)
+            .unwrap_or(self.entries.len());
+
+        self.entries.insert(
+            rank,
+            HighScore {
+                name: name.to_string(),
+                score,
+                seed: Some(seed),
+            },
+        );
+        self.entries.truncate(TABLE_SIZE);
+
+        Some(rank)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(scores: &[u32]) -> HighScores {
+        let mut rv = HighScores::default();
+        for (i, score) in scores.iter().enumerate() {
+            rv.insert(&format!("P{}", i), *score, i as u64);
+        }
+        rv
+    }
+
+    fn scores(table: &HighScores) -> Vec<u32> {
+        table.entries().iter().map(|entry| entry.score).collect()
+    }
+
+    #[test]
+    fn missing_files_give_an_empty_table() {
+        let path = std::env::temp_dir().join("space-invaders-test-no-such-high-scores");
+        assert!(HighScores::load_or_empty(&path).entries().is_empty());
+    }
+
+    #[test]
+    fn broken_files_give_an_empty_table() {
+        let path = std::env::temp_dir().join(format!("space-invaders-test-broken-{}", std::process::id()));
+        fs::write(&path, "not a high score file\n").unwrap();
+        let scores = HighScores::load_or_empty(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(scores.entries().is_empty());
+    }
+
+    #[test]
+    fn saves_and_loads_again() {
+        let path = std::env::temp_dir().join(format!("space-invaders-test-scores-{}", std::process::id()));
+        table(&[300, 100, 200]).save_to(&path).unwrap();
+        let loaded = HighScores::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(scores(&loaded), vec![300, 200, 100]);
+        assert_eq!(loaded.entries()[0].name, "P0");
+        assert_eq!(loaded.entries()[0].seed, Some(0));
+    }
+
+    #[test]
+    fn rejects_bad_headers_and_lines() {
+        let error = |source: &str| HighScores::parse(source).err().unwrap().to_string();
+
+        assert!(error("").contains("empty"));
+        assert!(error("high scores\nAAA 10 1\n").contains("Unrecognised header"));
+        assert!(error(&format!("{}\nAAA 10\n", HEADER)).contains("Malformed entry"));
+        assert!(error(&format!("{}\nAAA 10 1 2\n", HEADER)).contains("Malformed entry"));
+        assert!(HighScores::parse(&format!("{}\nAAA ten 1\n", HEADER)).is_err());
+    }
+
+    #[test]
+    fn reads_v1_files_without_seeds() {
+        let scores = HighScores::parse(&format!("{}\nAAA 10\nBBB 30\n", HEADER_V1)).unwrap();
+
+        assert_eq!(scores.entries()[0].name, "BBB");
+        assert_eq!(scores.entries()[0].score, 30);
+        assert_eq!(scores.entries()[0].seed, None);
+        assert!(HighScores::parse(&format!("{}\nAAA 10 5\n", HEADER_V1)).is_err());
+    }
+
+    #[test]
+    fn keeps_only_the_top_scores_when_reading() {
+        let lines = (1..=15).map(|score| format!("AAA {} -\n", score)).collect::<String>();
+        let scores = HighScores::parse(&format!("{}\n{}", HEADER, lines)).unwrap();
+
+        assert_eq!(scores.entries().len(), TABLE_SIZE);
+        assert_eq!(scores.entries()[0].score, 15);
+        assert_eq!(scores.entries()[TABLE_SIZE - 1].score, 6);
+    }
+
+    #[test]
+    fn ranks_new_scores_with_ties_going_to_the_first() {
+        let mut scores = table(&[300, 200, 100]);
+
+        assert_eq!(scores.insert("NEW", 200, 9), Some(2));
+        assert_eq!(scores.entries()[1].name, "P1");
+        assert_eq!(scores.entries()[2].name, "NEW");
+        assert_eq!(scores.insert("TOP", 400, 9), Some(0));
+        assert!(!scores.qualifies(0));
+        assert_eq!(scores.insert("NIL", 0, 9), None);
+    }
+
+    #[test]
+    fn drops_scores_off_the_bottom_of_a_full_table() {
+        let mut scores = table(&[100; TABLE_SIZE]);
+
+        assert!(!scores.qualifies(100));
+        assert_eq!(scores.insert("LOW", 100, 9), None);
+        assert!(scores.qualifies(101));
+        assert_eq!(scores.insert("NEW", 101, 9), Some(0));
+        assert_eq!(scores.entries().len(), TABLE_SIZE);
+        assert_eq!(scores.entries()[TABLE_SIZE - 1].name, format!("P{}", TABLE_SIZE - 2));
+    }
+}
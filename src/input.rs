@@ -0,0 +1,251 @@
+use failure::Error;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// A key press, independent of whatever terminal library read it.
+/// The loops turn these into their own `CtrlEvent`s.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Key {
+    Char(char),
+    Ctrl(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    Enter,
+    Backspace,
+    Delete,
+    Esc,
+}
+
+/// Where the loops get their key presses from.
+pub trait Input {
+    /// Everything pressed since the last call. Called once per frame.
+    fn keys(&mut self) -> Vec<Key>;
+}
+
+/// Reads keys from the terminal, without blocking.
+pub struct TermionInput {
+    reader: termion::AsyncReader,
+}
+
+/// Plays back a fixed list of key presses, one entry per frame.
+/// Once it runs out, nothing else is ever pressed.
+pub struct ScriptedInput {
+    frames: VecDeque<Vec<Key>>,
+}
+
+/// Replays key presses from a file, then quits once the file runs out.
+///
+/// Each line is a frame number followed by the keys pressed on that frame,
+/// e.g. `12 left space`. Frames without a line have nothing pressed.
+pub struct ReplayInput {
+    /// The frames with something pressed, in order, as (frame, keys).
+    frames: VecDeque<(usize, Vec<Key>)>,
+    frame: usize,
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Key::Char(' ') => write!(f, "space"),
+            Key::Char(c) => write!(f, "{}", c),
+            Key::Ctrl(c) => write!(f, "ctrl-{}", c),
+            Key::Up => write!(f, "up"),
+            Key::Down => write!(f, "down"),
+            Key::Left => write!(f, "left"),
+            Key::Right => write!(f, "right"),
+            Key::Enter => write!(f, "enter"),
+            Key::Backspace => write!(f, "backspace"),
+            Key::Delete => write!(f, "delete"),
+            Key::Esc => write!(f, "esc"),
+        }
+    }
+}
+
+impl FromStr for Key {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let key = match s {
+            "space" => Key::Char(' '),
+            "up" => Key::Up,
+            "down" => Key::Down,
+            "left" => Key::Left,
+            "right" => Key::Right,
+            "enter" => Key::Enter,
+            "backspace" => Key::Backspace,
+            "delete" => Key::Delete,
+            "esc" => Key::Esc,
+            s => {
+                let (ctrl, rest) = match s.strip_prefix("ctrl-") {
+                    Some(rest) => (true, rest),
+                    None => (false, s),
+                };
+
+                let mut chars = rest.chars();
+                match (chars.next(), chars.next(), ctrl) {
+                    (Some(c), None, true) => Key::Ctrl(c),
+                    (Some(c), None, false) => Key::Char(c),
+                    _ => return Err(format_err!("Unknown key: {:?}", s)),
+                }
+            }
+        };
+
+        Ok(key)
+    }
+}
+
+impl TermionInput {
+    pub fn new(reader: termion::AsyncReader) -> Self {
+        Self { reader }
+    }
+}
+
+impl Input for TermionInput {
+    fn keys(&mut self) -> Vec<Key> {
+        use termion::event::{Event, Key as TKey};
+        use termion::input::TermRead;
+
+        let mut rv = Vec::new();
+
+        for event in (&mut self.reader).events() {
+            let key = match event {
+                Ok(Event::Key(key)) => key,
+                Ok(_) => continue,
+                Err(e) => {
+                    error!("Stdin error: {}", e);
+                    continue;
+                }
+            };
+
+            let key = match key {
+                TKey::Char('\n') => Key::Enter,
+                TKey::Char(c) => Key::Char(c),
+                TKey::Ctrl(c) => Key::Ctrl(c),
+                TKey::Up => Key::Up,
+                TKey::Down => Key::Down,
+                TKey::Left => Key::Left,
+                TKey::Right => Key::Right,
+                TKey::Backspace => Key::Backspace,
+                TKey::Delete => Key::Delete,
+                TKey::Esc => Key::Esc,
+                _ => continue,
+            };
+
+            rv.push(key);
+        }
+
+        rv
+    }
+}
+
+impl ScriptedInput {
+    pub fn new(frames: Vec<Vec<Key>>) -> Self {
+        Self {
+            frames: frames.into(),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+impl Input for ScriptedInput {
+    fn keys(&mut self) -> Vec<Key> {
+        self.frames.pop_front().unwrap_or_default()
+    }
+}
+
+impl ReplayInput {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(source: &str) -> Result<Self, Error> {
+        let mut frames = Vec::new();
+        let mut frame = 0;
+
+        for (number, line) in source.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line.split_whitespace();
+            let at: usize = fields
+                .next()
+                .unwrap_or_default()
+                .parse()
+                .map_err(|e| format_err!("Line {}: bad frame number: {}", number + 1, e))?;
+
+            if at < frame {
+                return Err(format_err!("Line {}: frame {} is out of order", number + 1, at));
+            }
+
+            let keys = fields
+                .map(|key| key.parse().map_err(|e| format_err!("Line {}: {}", number + 1, e)))
+                .collect::<Result<Vec<Key>, Error>>()?;
+
+            frames.push((at, keys));
+            frame = at + 1;
+        }
+
+        Ok(Self {
+            frames: frames.into(),
+            frame: 0,
+        })
+    }
+}
+
+impl Input for ReplayInput {
+    fn keys(&mut self) -> Vec<Key> {
+        let frame = self.frame;
+        self.frame += 1;
+
+        match self.frames.front() {
+            None => vec![Key::Ctrl('c')],
+            Some((at, _)) if *at == frame => self.frames.pop_front().map(|(_, keys)| keys).unwrap_or_default(),
+            Some(_) => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replays_keys_on_their_frames_then_quits() {
+        let mut input = ReplayInput::parse("# a comment\n1 left space\n\n3 ctrl-x\n").unwrap();
+
+        assert_eq!(input.keys(), vec![]);
+        assert_eq!(input.keys(), vec![Key::Left, Key::Char(' ')]);
+        assert_eq!(input.keys(), vec![]);
+        assert_eq!(input.keys(), vec![Key::Ctrl('x')]);
+        assert_eq!(input.keys(), vec![Key::Ctrl('c')]);
+    }
+
+    #[test]
+    fn copes_with_far_off_frames() {
+        let mut input = ReplayInput::parse("0 up\n99999999999 left\n").unwrap();
+
+        assert_eq!(input.keys(), vec![Key::Up]);
+        assert_eq!(input.keys(), vec![]);
+    }
+
+    #[test]
+    fn rejects_frames_out_of_order() {
+        let error = |source| ReplayInput::parse(source).err().unwrap().to_string();
+
+        assert_eq!(error("5 left\n3 right\n"), "Line 2: frame 3 is out of order");
+        assert_eq!(error("5 left\n5 right\n"), "Line 2: frame 5 is out of order");
+        assert!(error("x left\n").starts_with("Line 1: bad frame number"));
+        assert_eq!(error("1 jump\n"), "Line 1: Unknown key: \"jump\"");
+    }
+}
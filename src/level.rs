@@ -0,0 +1,421 @@
+use crate::clock::TickRate;
+use crate::entities::{Bunker, Invader, InvaderKind, Player};
+use crate::game::game_loop::{FirePattern, FireSchedule};
+use crate::utils::{self, Coord};
+use failure::Error;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The levels shipped with the game, used when there are none on disk.
+const DEFAULT_LEVELS: &[&str] = &[
+    include_str!("../levels/01-first-contact.toml"),
+    include_str!("../levels/02-second-wave.toml"),
+    include_str!("../levels/03-swarm.toml"),
+];
+
+/// The smallest field a level can have. The menus, the high score table
+/// and the pause menu are drawn on the same screen as the levels, so they
+/// need this much room.
+pub const MIN_SIZE: Coord = Coord(40, 14);
+
+#[derive(Deserialize, Clone)]
+pub struct Formation {
+    /// Where the top left corner of the grid sits on the map.
+    pub origin: (usize, usize),
+    pub legend: HashMap<char, InvaderKind>,
+    pub grid: String,
+}
+
+/// Bunker cells drawn as ASCII art, with '#' for each cell.
+#[derive(Deserialize, Clone)]
+pub struct Bunkers {
+    pub origin: (usize, usize),
+    pub grid: String,
+}
+
+/// Scales how fast the swarm moves and how often it fires.
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+/// Changes made to the levels as they're loaded, from the command line.
+/// Kept in recordings, so a replay gets the same levels back.
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Rules {
+    pub difficulty: Difficulty,
+    /// Play every level on a field this big, instead of its own size.
+    pub size: Option<Coord>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct Level {
+    pub name: String,
+    pub width: usize,
+    pub height: usize,
+    /// Where the middle of the bottom of the player goes. Defaults to the
+    /// middle of the bottom row.
+    pub player: Option<(usize, usize)>,
+    /// Steps a second, with the full swarm alive.
+    pub swarm_speed: f32,
+    /// Seconds between volleys, or zero to hold fire.
+    pub fire_interval: f32,
+    pub fire_patterns: Vec<FirePattern>,
+    pub formation: Formation,
+    pub bunkers: Option<Bunkers>,
+    /// What's been changed since it was loaded.
+    #[serde(skip)]
+    pub rules: Rules,
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Difficulty::Easy => write!(f, "easy"),
+            Difficulty::Normal => write!(f, "normal"),
+            Difficulty::Hard => write!(f, "hard"),
+        }
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "easy" => Ok(Difficulty::Easy),
+            "normal" => Ok(Difficulty::Normal),
+            "hard" => Ok(Difficulty::Hard),
+            s => Err(format_err!("Unknown difficulty {:?}, try easy, normal or hard", s)),
+        }
+    }
+}
+
+impl Difficulty {
+    /// How much faster the swarm moves, and how much sooner it fires again.
+    fn factor(self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.75,
+            Difficulty::Normal => 1.0,
+            Difficulty::Hard => 1.3,
+        }
+    }
+}
+
+/// Reads a size written as `WIDTHxHEIGHT`, e.g. `60x20`.
+pub fn parse_size(s: &str) -> Result<Coord, Error> {
+    let bad = || format_err!("Size should look like 60x20, not {:?}", s);
+    let mut parts = s.splitn(2, 'x');
+
+    match (parts.next(), parts.next()) {
+        (Some(w), Some(h)) => Ok(Coord(w.parse().map_err(|_| bad())?, h.parse().map_err(|_| bad())?)),
+        _ => Err(bad()),
+    }
+}
+
+impl Level {
+    pub fn parse(source: &str) -> Result<Self, Error> {
+        let level: Level = toml::from_str(source)?;
+        level.validate()?;
+        Ok(level)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn defaults() -> Vec<Self> {
+        DEFAULT_LEVELS
+            .iter()
+            .map(|source| Self::parse(source).expect("Built-in level is invalid"))
+            .collect()
+    }
+
+    /// Where custom levels are looked for, under the XDG data directory.
+    pub fn dir() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("space-invaders").join("levels"))
+    }
+
+    /// Loads every level in `dir`, in file name order.
+    pub fn load_dir(dir: &Path) -> Result<Vec<Self>, Error> {
+        utils::load_dir(dir, Self::load)
+    }
+
+    /// The levels from the data directory if there are any, otherwise the built-in ones.
+    pub fn load_or_default() -> Vec<Self> {
+        match Self::dir() {
+            Some(dir) if dir.is_dir() => match Self::load_dir(&dir) {
+                Ok(levels) if !levels.is_empty() => levels,
+                Ok(_) => Self::defaults(),
+                Err(e) => {
+                    warn!("Using built-in levels, failed to load custom ones: {}", e);
+                    Self::defaults()
+                }
+            },
+            _ => Self::defaults(),
+        }
+    }
+
+    /// Puts the level called `start` first, and the rest after it in order,
+    /// the same as if the player had played up to it.
+    pub fn starting_at(mut levels: Vec<Level>, start: &str) -> Result<Vec<Level>, Error> {
+        let index = levels.iter().position(|level| level.name == start).ok_or_else(|| {
+            let names = levels.iter().map(|level| level.name.as_str()).collect::<Vec<_>>();
+            format_err!("No level called {:?}, there's {}", start, names.join(", "))
+        })?;
+
+        levels.rotate_left(index);
+        Ok(levels)
+    }
+
+    /// Applies the rules to a freshly loaded level. The swarm's speed and
+    /// fire rate change with the difficulty, and on a new size everything
+    /// stays centred, with the player and bunkers kept near the bottom.
+    pub fn with_rules(mut self, rules: Rules) -> Result<Self, Error> {
+        let factor = rules.difficulty.factor();
+        self.swarm_speed *= factor;
+        self.fire_interval /= factor;
+
+        if let Some(Coord(width, height)) = rules.size {
+            let dx = width as isize / 2 - self.width as isize / 2;
+            let dy = height as isize - self.height as isize;
+            let moved = |(x, y): (usize, usize), dy: isize| (x as isize + dx, y as isize + dy);
+            let name = self.name.clone();
+            let doesnt_fit = || format_err!("{} doesn't fit on a {}x{} field", name, width, height);
+
+            let formation = moved(self.formation.origin, 0);
+            let bunkers = self.bunkers.as_ref().map(|bunkers| moved(bunkers.origin, dy));
+            let player = self.player.map(|player| moved(player, dy));
+
+            for (x, y) in [Some(formation), bunkers, player].iter().flatten() {
+                if *x < 0 || *y < 0 {
+                    return Err(doesnt_fit());
+                }
+            }
+
+            self.formation.origin = (formation.0 as usize, formation.1 as usize);
+            if let (Some(bunkers), Some((x, y))) = (self.bunkers.as_mut(), bunkers) {
+                bunkers.origin = (x as usize, y as usize);
+            }
+            self.player = player.map(|(x, y)| (x as usize, y as usize));
+            self.width = width;
+            self.height = height;
+
+            self.validate().map_err(|e| format_err!("{} ({})", doesnt_fit(), e))?;
+        }
+
+        self.rules = rules;
+        Ok(self)
+    }
+
+    pub fn size(&self) -> Coord {
+        Coord(self.width, self.height)
+    }
+
+    pub fn player_start(&self) -> Coord {
+        match self.player {
+            Some((x, y)) => Coord(x, y),
+            None => Coord(self.width / 2, self.height - 1),
+        }
+    }
+
+    pub fn fire_schedule(&self, rate: TickRate) -> FireSchedule {
+        let interval = if self.fire_interval > 0.0 {
+            rate.ticks(self.fire_interval)
+        } else {
+            0
+        };
+
+        FireSchedule::new(self.fire_patterns.clone(), interval)
+    }
+
+    pub fn invaders(&self) -> Vec<Invader> {
+        let (x0, y0) = self.formation.origin;
+        let mut rv = Vec::new();
+
+        for (y, row) in self.formation.rows().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                if let Some(kind) = self.formation.legend.get(&c) {
+                    rv.push(Invader::new(*kind, Coord(x0 + x, y0 + y)));
+                }
+            }
+        }
+
+        rv
+    }
+
+    pub fn bunkers(&self) -> Vec<Bunker> {
+        let bunkers = match &self.bunkers {
+            Some(bunkers) => bunkers,
+            None => return Vec::new(),
+        };
+
+        let (x0, y0) = bunkers.origin;
+        let mut rv = Vec::new();
+
+        for (y, row) in bunkers.grid.lines().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                if c == '#' {
+                    rv.push(Bunker::new(Coord(x0 + x, y0 + y)));
+                }
+            }
+        }
+
+        rv
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        if self.width < MIN_SIZE.0 || self.height < MIN_SIZE.1 {
+            return Err(format_err!(
+                "Map must be at least {}x{}, got {}x{}",
+                MIN_SIZE.0,
+                MIN_SIZE.1,
+                self.width,
+                self.height
+            ));
+        }
+
+        // The player's position is the middle of its bottom row
+        let player = self.player_start();
+        let (left, right) = Player::SPRITE.reach();
+        let above = Player::SPRITE.origin.1;
+        if player.0 < left || player.0 + right >= self.width || player.1 < above || player.1 >= self.height {
+            return Err(format_err!("Player start ({}, {}) leaves the player off the map", player.0, player.1));
+        }
+        let top = player.1 - above;
+
+        if self.swarm_speed.is_nan() || self.swarm_speed <= 0.0 {
+            return Err(format_err!("swarm_speed must be more than 0"));
+        }
+
+        if self.fire_interval.is_nan() || self.fire_interval < 0.0 {
+            return Err(format_err!("fire_interval can't be negative"));
+        }
+
+        let (x0, y0) = self.formation.origin;
+        let mut count = 0;
+
+        for (y, row) in self.formation.rows().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                match c {
+                    '.' | ' ' => (),
+                    c if self.formation.legend.contains_key(&c) => {
+                        if x0 + x >= self.width || y0 + y >= top {
+                            return Err(format_err!(
+                                "Invader '{}' at ({}, {}) doesn't fit above the player",
+                                c,
+                                x0 + x,
+                                y0 + y
+                            ));
+                        }
+                        count += 1;
+                    }
+                    c => return Err(format_err!("Formation uses '{}', which isn't in the legend", c)),
+                }
+            }
+        }
+
+        if count == 0 {
+            return Err(format_err!("Formation has no invaders"));
+        }
+
+        if let Some(bunkers) = &self.bunkers {
+            let (x0, y0) = bunkers.origin;
+
+            for (y, row) in bunkers.grid.lines().enumerate() {
+                for (x, c) in row.chars().enumerate() {
+                    match c {
+                        '.' | ' ' => (),
+                        '#' => {
+                            if x0 + x >= self.width || y0 + y >= top {
+                                return Err(format_err!(
+                                    "Bunker at ({}, {}) doesn't fit above the player",
+                                    x0 + x,
+                                    y0 + y
+                                ));
+                            }
+                        }
+                        c => return Err(format_err!("Bunkers can only use '#', found '{}'", c)),
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Formation {
+    fn rows(&self) -> impl Iterator<Item = &str> {
+        self.grid.lines()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEVEL: &str = r#"
+name = "Test"
+width = 40
+height = 14
+
+swarm_speed = 5.0
+fire_interval = 1.0
+fire_patterns = ["random"]
+
+[formation]
+origin = [2, 1]
+legend = { C = "crab" }
+grid = """
+C.C.C
+"""
+"#;
+
+    fn error(source: &str) -> String {
+        match Level::parse(source) {
+            Ok(_) => panic!("Level should have been rejected"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn parses_a_valid_level() {
+        let level = Level::parse(LEVEL).unwrap();
+        assert_eq!(level.invaders().len(), 3);
+        assert_eq!(level.player_start().0, 20);
+        assert_eq!(level.player_start().1, 13);
+    }
+
+    #[test]
+    fn rejects_unknown_legend_chars() {
+        let source = LEVEL.replace("C.C.C", "C.X.C");
+        assert!(error(&source).contains("'X', which isn't in the legend"));
+    }
+
+    #[test]
+    fn rejects_a_formation_over_the_player() {
+        let source = LEVEL.replace("origin = [2, 1]", "origin = [2, 12]");
+        assert!(error(&source).contains("doesn't fit above the player"));
+    }
+
+    #[test]
+    fn rejects_a_formation_without_invaders() {
+        let source = LEVEL.replace("C.C.C", ".....");
+        assert!(error(&source).contains("no invaders"));
+    }
+
+    #[test]
+    fn rejects_fields_too_small_for_the_menus() {
+        let source = LEVEL.replace("height = 14", "height = 3");
+        assert!(error(&source).contains("at least 40x14"));
+    }
+}
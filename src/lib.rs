@@ -0,0 +1,31 @@
+//! Terminal Space Invaders.
+//!
+//! The game itself is in `game`. `GameLoop` can also be driven on its own,
+//! without a terminal: create one with `GameLoop::new`, feed it commands
+//! with `GameLoop::step`, and look at the entities after each frame.
+
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate failure;
+
+pub mod bot;
+pub mod clock;
+pub mod controls;
+pub mod entities;
+pub mod game;
+pub mod high_scores;
+pub mod input;
+pub mod level;
+pub mod map;
+pub mod render;
+pub mod replay;
+pub mod save;
+pub mod score;
+pub mod theme;
+pub mod utils;
+
+pub use crate::game::game_loop::{CtrlEvent, GameLoop};
+pub use crate::game::{Game, GameAction};
+pub use crate::level::Level;
+pub use crate::replay::Replay;
@@ -1,29 +1,322 @@
-#![feature(drain_filter)]
-#![feature(async_closure)]
+use failure::{format_err, Error};
+use space_invaders::clock::{TickRate, Timing};
+use space_invaders::input::{Input, ReplayInput, ScriptedInput, TermionInput};
+use space_invaders::level::{self, Rules};
+use space_invaders::render::{CastRenderer, HeadlessRenderer, Renderer, TermionRenderer};
+use space_invaders::theme::{ColourMode, Theme};
+use space_invaders::{Game, Level, Replay};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use termion::raw::IntoRawMode;
 
-#[macro_use]
-extern crate log;
-#[macro_use]
-extern crate failure;
+const USAGE: &str = "\
+Usage: space-invaders [OPTIONS]
 
-mod map;
-mod game;
-mod entities;
-mod utils;
+Game:
+  --size WxH            Play every level on a field this big, e.g. 60x20
+  --level NAME|FILE     Start on the named level, or play just the level in FILE
+  --difficulty LEVEL    easy, normal or hard
+  --seed N              Play every game with the same invaders, UFOs and fire
+  --tick-rate N         Ticks a second, the speed of the game logic
+  --render-rate N       Frames drawn a second, at most one per tick
 
-use termion::raw::IntoRawMode;
-use failure::Error;
-use crate::game::Game;
+Display:
+  --colour MODE         auto, none, 16, 256 or truecolor. auto goes by
+                        $TERM, $COLORTERM and $NO_COLOR
+  --theme NAME|FILE     classic, high-contrast, colour-blind, mono, one
+                        from ~/.config/space-invaders/themes, or a file
+  --full-redraw         Redraw the whole screen every frame, not just what
+                        changed, in case something else writes to it
+
+Recording:
+  --record FILE         Save each game, to play back with --replay
+  --replay FILE         Watch a game saved with --record
+  --cast FILE           Also save everything drawn as an asciinema recording
+
+Without a player:
+  --bot                 Let the computer play a game
+  --keys FILE           Read key presses from a file instead of the keyboard
+  --headless            Draw into memory instead of the terminal, and print
+                        the last frame at the end. Needs --keys, --replay or --bot
+
+  -h, --help            Show this message
+";
+
+/// Everything from the command line, before it's been checked.
+#[derive(Default)]
+struct Args {
+    size: Option<String>,
+    level: Option<String>,
+    difficulty: Option<String>,
+    seed: Option<String>,
+    tick_rate: Option<String>,
+    render_rate: Option<String>,
+    colour: Option<String>,
+    theme: Option<String>,
+    full_redraw: bool,
+    record: Option<PathBuf>,
+    replay: Option<PathBuf>,
+    cast: Option<PathBuf>,
+    keys: Option<PathBuf>,
+    bot: bool,
+    headless: bool,
+    help: bool,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, Error> {
+        let mut rv = Self::default();
+
+        while let Some(arg) = args.next() {
+            let mut value = |what: &str| {
+                args.next()
+                    .ok_or_else(|| format_err!("{} needs {}", arg, what))
+            };
+
+            match arg.as_str() {
+                "--size" => rv.size = Some(value("a size, like 60x20")?),
+                "--level" => rv.level = Some(value("a level name or file")?),
+                "--difficulty" => rv.difficulty = Some(value("easy, normal or hard")?),
+                "--seed" => rv.seed = Some(value("a number")?),
+                "--tick-rate" => rv.tick_rate = Some(value("a number of ticks a second")?),
+                "--render-rate" => rv.render_rate = Some(value("a number of frames a second")?),
+                "--colour" | "--color" => rv.colour = Some(value("auto, none, 16, 256 or truecolor")?),
+                "--theme" => rv.theme = Some(value("a theme name or file")?),
+                "--full-redraw" => rv.full_redraw = true,
+                "--record" => rv.record = Some(value("a file to write")?.into()),
+                "--replay" => rv.replay = Some(value("a file to read")?.into()),
+                "--cast" => rv.cast = Some(value("a file to write")?.into()),
+                "--keys" => rv.keys = Some(value("a file to read")?.into()),
+                "--bot" => rv.bot = true,
+                "--headless" => rv.headless = true,
+                "-h" | "--help" => rv.help = true,
+                arg => return Err(format_err!("Unknown argument: {}", arg)),
+            }
+        }
+
+        Ok(rv)
+    }
+
+    /// Turns the arguments into settings, refusing any that don't make
+    /// sense together.
+    fn options(self) -> Result<Options, Error> {
+        // A replay brings its own game, anything that changes it would
+        // only make it play out differently
+        if self.replay.is_some() {
+            let clashes = [
+                ("--seed", self.seed.is_some()),
+                ("--size", self.size.is_some()),
+                ("--difficulty", self.difficulty.is_some()),
+                ("--tick-rate", self.tick_rate.is_some()),
+                ("--bot", self.bot),
+            ];
+
+            if let Some((flag, _)) = clashes.iter().find(|(_, set)| *set) {
+                return Err(format_err!("{} can't be used with --replay, the replay has its own", flag));
+            }
+        }
+
+        if self.bot && self.keys.is_some() {
+            return Err(format_err!("--bot plays by itself, it can't be used with --keys"));
+        }
+
+        if self.headless && self.keys.is_none() && self.replay.is_none() && !self.bot {
+            return Err(format_err!("--headless needs --keys, --replay or --bot to play"));
+        }
+
+        let mut timing = Timing::default();
+
+        if let Some(n) = &self.tick_rate {
+            let n = n.parse().map_err(|_| format_err!("--tick-rate needs a whole number, got {:?}", n))?;
+            timing.tick_rate = TickRate::new(n)?;
+        }
+
+        if let Some(n) = &self.render_rate {
+            timing.render_rate = match n.parse() {
+                Ok(n) if n > 0 => n,
+                _ => return Err(format_err!("--render-rate needs a whole number above 0, got {:?}", n)),
+            };
+        }
+
+        // Nobody's watching, so don't wait around
+        timing.simulated = self.headless;
+
+        let seed = match &self.seed {
+            Some(n) => Some(n.parse().map_err(|_| format_err!("--seed needs a whole number, got {:?}", n))?),
+            None => None,
+        };
+
+        let rules = Rules {
+            difficulty: match &self.difficulty {
+                Some(difficulty) => difficulty.parse()?,
+                None => Default::default(),
+            },
+            size: match &self.size {
+                Some(size) => Some(level::parse_size(size)?),
+                None => None,
+            },
+        };
+
+        let colours = match &self.colour {
+            Some(mode) => mode.parse()?,
+            None => ColourMode::detect(),
+        };
 
-fn main() -> Result<(), Error> {
+        let theme = match self.theme.as_deref() {
+            Some(file) if file.ends_with(".toml") || Path::new(file).is_file() => Theme::load(Path::new(file))
+                .map_err(|e| format_err!("Can't load theme {}: {}", file, e))?,
+            Some(name) => Theme::named(name)?,
+            None => Theme::named("classic")?,
+        };
+
+        let replay = match &self.replay {
+            Some(path) => Some(
+                Replay::load(path).map_err(|e| format_err!("Can't read replay {}: {}", path.display(), e))?,
+            ),
+            None => None,
+        };
+
+        let levels = Self::levels(self.level.as_deref(), replay.is_none())?;
+
+        // Replays set up their own levels, so only the others get the rules
+        let levels = match &replay {
+            Some(_) => levels,
+            None => levels
+                .into_iter()
+                .map(|level| level.with_rules(rules))
+                .collect::<Result<_, _>>()?,
+        };
+
+        Ok(Options {
+            levels,
+            seed,
+            timing,
+            colours,
+            theme,
+            full_redraw: self.full_redraw,
+            record: self.record,
+            replay,
+            cast: self.cast,
+            keys: self.keys,
+            bot: self.bot,
+            headless: self.headless,
+        })
+    }
+
+    /// The levels to play. A file is played on its own, a name picks where
+    /// to start in the usual ones. A replay finds its own starting level.
+    fn levels(level: Option<&str>, pick_start: bool) -> Result<Vec<Level>, Error> {
+        match level {
+            Some(file) if file.ends_with(".toml") || Path::new(file).is_file() => {
+                let level = Level::load(Path::new(file))
+                    .map_err(|e| format_err!("Can't load level {}: {}", file, e))?;
+                Ok(vec![level])
+            }
+            Some(_) if !pick_start => Err(format_err!(
+                "--level can only give a level file with --replay, the replay starts where it started"
+            )),
+            Some(name) => Level::starting_at(Level::load_or_default(), name),
+            None => Ok(Level::load_or_default()),
+        }
+    }
+}
+
+struct Options {
+    levels: Vec<Level>,
+    seed: Option<u64>,
+    timing: Timing,
+    colours: ColourMode,
+    theme: Theme,
+    full_redraw: bool,
+    record: Option<PathBuf>,
+    replay: Option<Replay>,
+    cast: Option<PathBuf>,
+    keys: Option<PathBuf>,
+    bot: bool,
+    headless: bool,
+}
+
+fn main() {
     env_logger::init();
 
-    // let args: Vec<String> = std::env::args().collect();
-    let mut input = termion::async_stdin();
-    let mut output = std::io::stdout().into_raw_mode()?;
-    
-    let mut game = Game::new(&mut input, &mut output);
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) if args.help => {
+            print!("{}", USAGE);
+            return;
+        }
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("space-invaders: {}\nTry --help for the options", e);
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(e) = args.options().and_then(run) {
+        eprintln!("space-invaders: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run(options: Options) -> Result<(), Error> {
+    let mut input: Box<dyn Input> = match &options.keys {
+        Some(path) => Box::new(
+            ReplayInput::load(path).map_err(|e| format_err!("Can't read keys {}: {}", path.display(), e))?,
+        ),
+        // Nothing to press, the game plays itself
+        None if options.headless => Box::new(ScriptedInput::new(Vec::new())),
+        None => Box::new(TermionInput::new(termion::async_stdin())),
+    };
+
+    if options.headless {
+        let mut output = HeadlessRenderer::default();
+        play(input.as_mut(), &mut output, options)?;
+
+        for line in output.frame() {
+            println!("{}", line);
+        }
+    } else {
+        let mut output = TermionRenderer::new(std::io::stdout().into_raw_mode()?)
+            .with_theme(options.theme.clone(), options.colours);
+        if options.full_redraw {
+            output = output.with_full_redraw();
+        }
+        play(input.as_mut(), &mut output, options)?;
+    }
+
+    Ok(())
+}
+
+fn play(input: &mut dyn Input, out: &mut dyn Renderer, options: Options) -> Result<(), Error> {
+    let mut cast;
+    let out = match &options.cast {
+        Some(path) => {
+            let file = File::create(path)
+                .map_err(|e| format_err!("Can't write cast {}: {}", path.display(), e))?;
+            // Headless games still need a size, make it a typical terminal
+            let size = termion::terminal_size().unwrap_or((80, 24));
+            cast = CastRenderer::new(out, BufWriter::new(file), size)?
+                .with_theme(options.theme.clone(), options.colours);
+            &mut cast as &mut dyn Renderer
+        }
+        None => out,
+    };
+
+    let mut game = Game::new(input, out, options.levels)?.with_timing(options.timing);
+
+    if let Some(seed) = options.seed {
+        game = game.with_seed(seed);
+    }
+    if let Some(path) = options.record {
+        game = game.with_recording(path);
+    }
+    if let Some(replay) = options.replay {
+        game = game.with_replay(replay)?;
+    }
+    if options.bot {
+        game = game.with_bot();
+    }
+
     game.run();
-    
     Ok(())
 }
@@ -0,0 +1,728 @@
+use crate::map::Map;
+use crate::theme::{ColourMode, Element, Theme, RESET};
+use crate::utils::{Coord, Screen, Tile};
+use failure::Error;
+use std::io::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use termion::cursor::Goto;
+
+/// Rows kept free under the play field for the HUD, on every screen, so
+/// nothing jumps about when a message comes and goes.
+pub const HUD_ROWS: usize = 2;
+
+/// A row of text, in runs that are each drawn in one style.
+pub type Line = Vec<(Element, String)>;
+
+/// Everything shown under the play field.
+pub struct Hud {
+    pub score: u32,
+    pub multiplier: u32,
+    pub lives: u8,
+    pub wave: usize,
+    pub message: Option<String>,
+}
+
+/// Draws whole frames. The loops describe what to show, and the
+/// renderer decides how it ends up on screen (or not).
+pub trait Renderer {
+    /// The play field, with a glyph for each tile, and the HUD underneath.
+    fn draw_game(
+        &mut self,
+        screen: &Screen,
+        map: &Map<Tile>,
+        glyphs: &Map<char>,
+        hud: &Hud,
+    ) -> Result<(), Error>;
+
+    /// A list of menu items, along with whether each is selected.
+    fn draw_menu(&mut self, screen: &Screen, items: &[(&str, bool)]) -> Result<(), Error>;
+
+    /// An empty screen, with each line of text centred on its row.
+    fn draw_text(&mut self, screen: &Screen, lines: &[(usize, String)]) -> Result<(), Error>;
+
+    /// Text drawn over the last frame, centred like `draw_text`. Only the
+    /// panel behind the text is covered, the frame shows around it.
+    fn draw_overlay(&mut self, screen: &Screen, lines: &[(usize, String)]) -> Result<(), Error>;
+
+    /// Shown instead of everything else while the terminal is smaller
+    /// than `need`, the size from `required_size`.
+    fn draw_too_small(&mut self, need: Coord) -> Result<(), Error>;
+
+    /// Debug info, drawn over whatever is on screen.
+    fn draw_status(&mut self, text: &str) -> Result<(), Error>;
+
+    /// How much room there is to draw in, if there's a limit. Asked every
+    /// frame, so it follows the terminal as it's resized.
+    fn terminal_size(&self) -> Option<Coord> {
+        None
+    }
+
+    /// Called once everything for a frame has been drawn, with the time
+    /// since the game started.
+    fn end_frame(&mut self, _time: Duration) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Puts the terminal back the way we found it.
+    fn finish(&mut self) -> Result<(), Error>;
+}
+
+/// Draws to a terminal with termion escape codes, centred in it. Each
+/// frame is built up in memory, then only what changed is written out.
+pub struct TermionRenderer<W: Write> {
+    out: W,
+    /// Draw as if the terminal were this big, instead of asking it.
+    size: Option<Coord>,
+    /// Where the last frame's top left corner went, for the overlays.
+    margins: Coord,
+    theme: Theme,
+    colours: ColourMode,
+    /// The frame being drawn, the size of the terminal.
+    next: Map<Cell>,
+    /// What's on screen, from the last frame sent.
+    shown: Map<Cell>,
+    /// Whether anything's been drawn since the last frame was sent.
+    pending: bool,
+    full_redraw: bool,
+}
+
+/// One character on the terminal, and what it was drawn for. Cells
+/// nothing was drawn in are left in the terminal's own colours.
+#[derive(Clone, Copy, PartialEq)]
+struct Cell {
+    glyph: char,
+    element: Option<Element>,
+}
+
+const BLANK: Cell = Cell {
+    glyph: ' ',
+    element: None,
+};
+
+/// Passes everything on to another renderer, while also recording it as
+/// an asciicast v2 file that asciinema can play back.
+pub struct CastRenderer<'a, W: Write> {
+    inner: &'a mut dyn Renderer,
+    /// Draws each frame as it would appear in a terminal.
+    frame: TermionRenderer<Vec<u8>>,
+    out: W,
+}
+
+/// Draws into a grid of strings, so no terminal is needed.
+#[derive(Default)]
+pub struct HeadlessRenderer {
+    frame: Vec<String>,
+}
+
+impl Hud {
+    pub fn lines(&self) -> Vec<Line> {
+        let gap = || (Element::Text, "  ".to_string());
+        let mut rv = vec![vec![
+            (Element::Score, format!("Score: {:<8} x{}", self.score, self.multiplier)),
+            gap(),
+            (Element::Lives, format!("Lives: {}", self.lives)),
+            gap(),
+            (Element::Wave, format!("Wave: {}", self.wave)),
+        ]];
+
+        if let Some(message) = &self.message {
+            rv.push(vec![(Element::Message, message.clone())]);
+        }
+
+        rv
+    }
+}
+
+/// The line's text, without any styles.
+fn plain(line: &[(Element, String)]) -> String {
+    line.iter().map(|(_, text)| text.as_str()).collect()
+}
+
+/// Menus mark the item under the cursor with a '>' in front.
+fn text_element(text: &str) -> Element {
+    if text.starts_with("> ") {
+        Element::Selected
+    } else {
+        Element::Text
+    }
+}
+
+/// The terminal size needed to show a screen: the field, its border, and
+/// the HUD underneath.
+pub fn required_size(screen: &Screen) -> Coord {
+    Coord(screen.size().0 + 2, screen.size().1 + 2 + HUD_ROWS)
+}
+
+/// The glyphs, each styled by the tile it's drawn for.
+fn field_rows(map: &Map<Tile>, glyphs: &Map<char>) -> Vec<Line> {
+    (0..glyphs.height())
+        .map(|y| {
+            let mut line: Line = Vec::new();
+
+            for x in 0..glyphs.width() {
+                let element = Element::from(map[(x, y)]);
+                match line.last_mut() {
+                    Some((last, text)) if *last == element => text.push(glyphs[(x, y)]),
+                    _ => line.push((element, glyphs[(x, y)].to_string())),
+                }
+            }
+
+            line
+        })
+        .collect()
+}
+
+fn text_rows(size: &Coord, lines: &[(usize, String)]) -> Vec<Line> {
+    (0..size.1)
+        .map(|y| match lines.iter().find(|(line_y, _)| *line_y == y) {
+            Some((_, text)) => {
+                let text: String = text.chars().take(size.0).collect();
+                let left = (size.0 - text.chars().count()) / 2;
+                let right = size.0 - left - text.chars().count();
+                vec![
+                    (Element::Text, " ".repeat(left)),
+                    (text_element(&text), text),
+                    (Element::Text, " ".repeat(right)),
+                ]
+            }
+            None => vec![(Element::Text, " ".repeat(size.0))],
+        })
+        .collect()
+}
+
+fn menu_rows(size: &Coord, items: &[(&str, bool)]) -> Vec<Line> {
+    let top = (size.1 / 2).saturating_sub(1);
+
+    let lines = items
+        .iter()
+        .enumerate()
+        .map(|(i, (label, selected))| {
+            (top + i, format!("{} {}", if *selected { ">" } else { " " }, label))
+        })
+        .collect::<Vec<_>>();
+
+    // Line the items up on the left, rather than centring each one
+    let width = lines.iter().map(|(_, text)| text.len()).max().unwrap_or(0);
+    let lines = lines
+        .into_iter()
+        .map(|(y, text)| (y, format!("{:<width$}", text, width = width)))
+        .collect::<Vec<_>>();
+
+    text_rows(size, &lines)
+}
+
+/// Where each overlay line goes in the play field, as (x, y, text). The
+/// lines are padded out to the same width, so they make a solid panel.
+fn overlay_cells(size: &Coord, lines: &[(usize, String)]) -> Vec<(usize, usize, Line)> {
+    let longest = lines.iter().map(|(_, text)| text.chars().count()).max().unwrap_or(0);
+    let width = std::cmp::min(longest + 2, size.0);
+    let left = (size.0 - width) / 2;
+
+    lines
+        .iter()
+        .filter(|(y, _)| *y < size.1)
+        .map(|(y, text)| {
+            let text: String = text.chars().take(width).collect();
+            let pad = (width - text.chars().count()) / 2;
+            let text = format!("{:pad$}{:<rest$}", "", text, pad = pad, rest = width - pad);
+            (left, *y, vec![(text_element(text.trim_start()), text)])
+        })
+        .collect()
+}
+
+/// Wraps the rows in a border.
+fn boxed(rows: Vec<Line>, width: usize) -> Vec<Line> {
+    let border = vec![(Element::Border, format!("+{}+", "-".repeat(width)))];
+    let side = || (Element::Border, "|".to_string());
+    let mut rv = Vec::with_capacity(rows.len() + 2);
+
+    rv.push(border.clone());
+    rv.extend(rows.into_iter().map(|mut row| {
+        row.insert(0, side());
+        row.push(side());
+        row
+    }));
+    rv.push(border);
+
+    rv
+}
+
+impl<W: Write> TermionRenderer<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            size: None,
+            margins: Coord(0, 0),
+            theme: Theme::default(),
+            colours: ColourMode::Off,
+            next: Map::new(Coord(0, 0), BLANK),
+            shown: Map::new(Coord(0, 0), BLANK),
+            pending: false,
+            full_redraw: false,
+        }
+    }
+
+    /// Draws for a terminal of the given size, whatever size the real one is.
+    pub fn with_size(out: W, size: Coord) -> Self {
+        Self {
+            size: Some(size),
+            ..Self::new(out)
+        }
+    }
+
+    /// Draws in colour, as far as the terminal can show it.
+    pub fn with_theme(mut self, theme: Theme, colours: ColourMode) -> Self {
+        self.theme = theme;
+        self.colours = colours;
+        self
+    }
+
+    /// Clears the terminal and draws every cell, every frame, rather than
+    /// only the ones that changed. Slower, but puts right anything else
+    /// that writes to the terminal.
+    pub fn with_full_redraw(mut self) -> Self {
+        self.full_redraw = true;
+        self
+    }
+
+    /// The cells' text, with escape codes wherever the style changes.
+    fn paint(&self, cells: &[Cell]) -> String {
+        let mut rv = String::with_capacity(cells.len());
+        let mut current = String::new();
+
+        for cell in cells {
+            let escape = match cell.element {
+                Some(element) => self.theme.style(element).escape(self.colours),
+                None => String::new(),
+            };
+
+            if escape != current {
+                if !current.is_empty() {
+                    rv.push_str(RESET);
+                }
+                rv.push_str(&escape);
+                current = escape;
+            }
+            rv.push(cell.glyph);
+        }
+
+        if !current.is_empty() {
+            rv.push_str(RESET);
+        }
+
+        rv
+    }
+
+    /// Starts a new frame, blank and the size of the terminal as it is now.
+    fn start_frame(&mut self, size: Coord) {
+        self.next = Map::new(size, BLANK);
+        self.pending = true;
+    }
+
+    /// Writes the line into the frame, cut off at the edge of the terminal.
+    fn put(&mut self, x: usize, y: usize, line: &[(Element, String)]) {
+        if y >= self.next.height() {
+            return;
+        }
+
+        let cells = line
+            .iter()
+            .flat_map(|(element, text)| text.chars().map(move |glyph| (*element, glyph)));
+
+        for (x, (element, glyph)) in (x..self.next.width()).zip(cells) {
+            self.next[(x, y)] = Cell {
+                glyph,
+                element: Some(element),
+            };
+        }
+    }
+
+    fn write_lines(&mut self, screen: &Screen, lines: &[Line]) -> Result<(), Error> {
+        // Centre it all afresh each frame, in case the terminal was resized
+        let need = required_size(screen);
+        let size = self.terminal_size().unwrap_or(need);
+        let margins = Coord(size.0.saturating_sub(need.0) / 2, size.1.saturating_sub(need.1) / 2);
+        self.margins = margins;
+
+        self.start_frame(size);
+        for (y, line) in lines.iter().enumerate() {
+            self.put(margins.0, margins.1 + y, line);
+        }
+
+        Ok(())
+    }
+
+    /// Sends the frame to the terminal. Only the cells that changed since
+    /// the last one are written, unless the terminal changed size, when
+    /// it's cleared and drawn from scratch.
+    fn flush_frame(&mut self) -> Result<(), Error> {
+        use std::fmt::Write;
+
+        if !self.pending {
+            return Ok(());
+        }
+
+        let size = self.next.dimensions;
+        let full = self.full_redraw || self.shown.dimensions != size;
+
+        // A cleared terminal is blank everywhere
+        let cleared;
+        let before = if full {
+            cleared = Map::new(size, BLANK);
+            &cleared
+        } else {
+            &self.shown
+        };
+
+        let mut buff = String::new();
+        if full {
+            write!(&mut buff, "{}", termion::clear::All)?;
+        }
+
+        for y in 0..size.1 {
+            let row = y * size.0;
+            let mut x = 0;
+
+            while x < size.0 {
+                if before.grid[row + x] == self.next.grid[row + x] {
+                    x += 1;
+                    continue;
+                }
+
+                // Write the whole run of changed cells in one go
+                let start = x;
+                while x < size.0 && before.grid[row + x] != self.next.grid[row + x] {
+                    x += 1;
+                }
+
+                let cells = &self.next.grid[row + start..row + x];
+                write!(&mut buff, "{}{}", Goto(start as u16 + 1, y as u16 + 1), self.paint(cells))?;
+            }
+        }
+
+        if !buff.is_empty() {
+            write!(&mut buff, "{}", Goto(1, 1))?;
+            write!(self.out, "{}", buff)?;
+            self.out.flush()?;
+        }
+
+        std::mem::swap(&mut self.shown, &mut self.next);
+        self.pending = false;
+
+        Ok(())
+    }
+}
+
+impl<W: Write> Renderer for TermionRenderer<W> {
+    fn draw_game(
+        &mut self,
+        screen: &Screen,
+        map: &Map<Tile>,
+        glyphs: &Map<char>,
+        hud: &Hud,
+    ) -> Result<(), Error> {
+        let mut lines = boxed(field_rows(map, glyphs), glyphs.width());
+        lines.append(&mut hud.lines());
+        self.write_lines(screen, &lines)
+    }
+
+    fn draw_menu(&mut self, screen: &Screen, items: &[(&str, bool)]) -> Result<(), Error> {
+        let lines = boxed(menu_rows(screen.size(), items), screen.size().0);
+        self.write_lines(screen, &lines)
+    }
+
+    fn draw_text(&mut self, screen: &Screen, lines: &[(usize, String)]) -> Result<(), Error> {
+        let lines = boxed(text_rows(screen.size(), lines), screen.size().0);
+        self.write_lines(screen, &lines)
+    }
+
+    fn draw_overlay(&mut self, screen: &Screen, lines: &[(usize, String)]) -> Result<(), Error> {
+        let margins = self.margins;
+
+        // Inside the border
+        for (x, y, text) in overlay_cells(screen.size(), lines) {
+            self.put(margins.0 + 1 + x, margins.1 + 1 + y, &text);
+        }
+
+        Ok(())
+    }
+
+    fn draw_too_small(&mut self, need: Coord) -> Result<(), Error> {
+        let size = self.terminal_size().unwrap_or(need);
+        let lines = [
+            "Terminal too small".to_string(),
+            format!("Need {}x{}, have {}x{}", need.0, need.1, size.0, size.1),
+        ];
+
+        self.start_frame(size);
+
+        // As near the middle as will fit
+        let top = size.1.saturating_sub(lines.len()) / 2;
+        for (y, line) in lines.iter().enumerate() {
+            let line: String = line.chars().take(size.0).collect();
+            let left = (size.0 - line.chars().count()) / 2;
+            self.put(left, top + y, &[(Element::Text, line)]);
+        }
+
+        Ok(())
+    }
+
+    fn draw_status(&mut self, text: &str) -> Result<(), Error> {
+        self.put(0, 0, &[(Element::Text, text.to_string())]);
+        Ok(())
+    }
+
+    fn terminal_size(&self) -> Option<Coord> {
+        self.size.or_else(|| {
+            termion::terminal_size()
+                .ok()
+                .map(|(width, height)| Coord(width as usize, height as usize))
+        })
+    }
+
+    fn end_frame(&mut self, _time: Duration) -> Result<(), Error> {
+        self.flush_frame()
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.flush_frame()?;
+        write!(self.out, "{}", termion::cursor::Show)?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Quotes a string for JSON.
+fn json_string(s: &str) -> String {
+    let mut rv = String::with_capacity(s.len() + 2);
+    rv.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => rv.push_str("\\\""),
+            '\\' => rv.push_str("\\\\"),
+            '\n' => rv.push_str("\\n"),
+            '\r' => rv.push_str("\\r"),
+            c if c.is_control() => rv.push_str(&format!("\\u{:04x}", c as u32)),
+            c => rv.push(c),
+        }
+    }
+
+    rv.push('"');
+    rv
+}
+
+impl<'a, W: Write> CastRenderer<'a, W> {
+    /// Starts the recording with a terminal of the given size.
+    pub fn new(inner: &'a mut dyn Renderer, mut out: W, size: (u16, u16)) -> Result<Self, Error> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or(0);
+
+        writeln!(
+            out,
+            "{{\"version\": 2, \"width\": {}, \"height\": {}, \"timestamp\": {}, \"title\": \"Space Invaders\"}}",
+            size.0, size.1, timestamp
+        )?;
+
+        Ok(Self {
+            inner,
+            frame: TermionRenderer::with_size(Vec::new(), Coord(size.0 as usize, size.1 as usize)),
+            out,
+        })
+    }
+
+    /// Records the frames in colour, the same as they're shown.
+    pub fn with_theme(mut self, theme: Theme, colours: ColourMode) -> Self {
+        self.frame = self.frame.with_theme(theme, colours);
+        self
+    }
+}
+
+impl<W: Write> Renderer for CastRenderer<'_, W> {
+    fn draw_game(
+        &mut self,
+        screen: &Screen,
+        map: &Map<Tile>,
+        glyphs: &Map<char>,
+        hud: &Hud,
+    ) -> Result<(), Error> {
+        self.frame.draw_game(screen, map, glyphs, hud)?;
+        self.inner.draw_game(screen, map, glyphs, hud)
+    }
+
+    fn draw_menu(&mut self, screen: &Screen, items: &[(&str, bool)]) -> Result<(), Error> {
+        self.frame.draw_menu(screen, items)?;
+        self.inner.draw_menu(screen, items)
+    }
+
+    fn draw_text(&mut self, screen: &Screen, lines: &[(usize, String)]) -> Result<(), Error> {
+        self.frame.draw_text(screen, lines)?;
+        self.inner.draw_text(screen, lines)
+    }
+
+    fn draw_overlay(&mut self, screen: &Screen, lines: &[(usize, String)]) -> Result<(), Error> {
+        self.frame.draw_overlay(screen, lines)?;
+        self.inner.draw_overlay(screen, lines)
+    }
+
+    fn draw_too_small(&mut self, need: Coord) -> Result<(), Error> {
+        self.frame.draw_too_small(need)?;
+        self.inner.draw_too_small(need)
+    }
+
+    // Debug info stays out of the recording
+    fn draw_status(&mut self, text: &str) -> Result<(), Error> {
+        self.inner.draw_status(text)
+    }
+
+    // It's whatever's really being drawn to that matters
+    fn terminal_size(&self) -> Option<Coord> {
+        self.inner.terminal_size()
+    }
+
+    fn end_frame(&mut self, time: Duration) -> Result<(), Error> {
+        self.frame.end_frame(time)?;
+
+        if !self.frame.out.is_empty() {
+            let data = String::from_utf8_lossy(&self.frame.out);
+            writeln!(
+                self.out,
+                "[{:.6}, \"o\", {}]",
+                time.as_secs_f64(),
+                json_string(&data)
+            )?;
+            self.frame.out.clear();
+        }
+
+        self.inner.end_frame(time)
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.out.flush()?;
+        self.inner.finish()
+    }
+}
+
+impl HeadlessRenderer {
+    /// The last frame drawn, one string per row.
+    pub fn frame(&self) -> &[String] {
+        &self.frame
+    }
+}
+
+impl Renderer for HeadlessRenderer {
+    fn draw_game(
+        &mut self,
+        _screen: &Screen,
+        map: &Map<Tile>,
+        glyphs: &Map<char>,
+        hud: &Hud,
+    ) -> Result<(), Error> {
+        let mut lines = boxed(field_rows(map, glyphs), glyphs.width());
+        lines.append(&mut hud.lines());
+        self.frame = lines.iter().map(|line| plain(line)).collect();
+        Ok(())
+    }
+
+    fn draw_menu(&mut self, screen: &Screen, items: &[(&str, bool)]) -> Result<(), Error> {
+        let lines = boxed(menu_rows(screen.size(), items), screen.size().0);
+        self.frame = lines.iter().map(|line| plain(line)).collect();
+        Ok(())
+    }
+
+    fn draw_text(&mut self, screen: &Screen, lines: &[(usize, String)]) -> Result<(), Error> {
+        let lines = boxed(text_rows(screen.size(), lines), screen.size().0);
+        self.frame = lines.iter().map(|line| plain(line)).collect();
+        Ok(())
+    }
+
+    fn draw_overlay(&mut self, screen: &Screen, lines: &[(usize, String)]) -> Result<(), Error> {
+        for (x, y, text) in overlay_cells(screen.size(), lines) {
+            // Inside the border
+            if let Some(row) = self.frame.get_mut(y + 1) {
+                let mut chars: Vec<char> = row.chars().collect();
+                for (i, c) in plain(&text).chars().enumerate() {
+                    if let Some(cell) = chars.get_mut(x + 1 + i) {
+                        *cell = c;
+                    }
+                }
+                *row = chars.into_iter().collect();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn draw_too_small(&mut self, need: Coord) -> Result<(), Error> {
+        self.frame = vec![format!("Terminal too small, need {}x{}", need.0, need.1)];
+        Ok(())
+    }
+
+    fn draw_status(&mut self, _text: &str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use termion::clear;
+
+    /// Draws the text at the top left of a blank frame and sends it,
+    /// returning what was written to the terminal.
+    fn frame(renderer: &mut TermionRenderer<Vec<u8>>, text: &str) -> String {
+        let size = renderer.terminal_size().unwrap();
+        renderer.start_frame(size);
+        renderer.put(0, 0, &[(Element::Text, text.to_string())]);
+        renderer.end_frame(Duration::from_secs(0)).unwrap();
+        String::from_utf8(std::mem::take(&mut renderer.out)).unwrap()
+    }
+
+    #[test]
+    fn draws_the_first_frame_from_scratch() {
+        let mut renderer = TermionRenderer::with_size(Vec::new(), Coord(5, 2));
+
+        assert_eq!(frame(&mut renderer, "ab"), format!("{}{}ab{}", clear::All, Goto(1, 1), Goto(1, 1)));
+    }
+
+    #[test]
+    fn writes_nothing_for_the_same_frame() {
+        let mut renderer = TermionRenderer::with_size(Vec::new(), Coord(5, 2));
+        frame(&mut renderer, "ab");
+
+        assert_eq!(frame(&mut renderer, "ab"), "");
+    }
+
+    #[test]
+    fn writes_only_the_cells_that_changed() {
+        let mut renderer = TermionRenderer::with_size(Vec::new(), Coord(5, 2));
+        frame(&mut renderer, "abc");
+
+        assert_eq!(frame(&mut renderer, "axc"), format!("{}x{}", Goto(2, 1), Goto(1, 1)));
+        assert_eq!(frame(&mut renderer, "a"), format!("{}  {}", Goto(2, 1), Goto(1, 1)));
+    }
+
+    #[test]
+    fn clears_when_the_terminal_changes_size() {
+        let mut renderer = TermionRenderer::with_size(Vec::new(), Coord(5, 2));
+        frame(&mut renderer, "ab");
+        renderer.size = Some(Coord(6, 2));
+
+        assert_eq!(frame(&mut renderer, "ab"), format!("{}{}ab{}", clear::All, Goto(1, 1), Goto(1, 1)));
+        assert_eq!(frame(&mut renderer, "ab"), "");
+    }
+
+    #[test]
+    fn clears_every_frame_when_asked_to() {
+        let mut renderer = TermionRenderer::with_size(Vec::new(), Coord(5, 2)).with_full_redraw();
+        frame(&mut renderer, "ab");
+
+        assert_eq!(frame(&mut renderer, "ab"), format!("{}{}ab{}", clear::All, Goto(1, 1), Goto(1, 1)));
+    }
+}
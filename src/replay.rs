@@ -0,0 +1,288 @@
+use crate::clock::TickRate;
+use crate::game::game_loop::CtrlEvent;
+use crate::level::{self, Level, Rules};
+use failure::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// First line of a replay file. Bump the number if the format changes, or
+/// the game plays differently so older replays would go another way.
+const HEADER: &str = "space-invaders replay v3";
+
+/// The start of every replay's header, whatever its version.
+const HEADER_PREFIX: &str = "space-invaders replay v";
+
+/// Everything the player did in one game, enough to play it back exactly.
+///
+/// The file is plain text: the header, then `seed`, `level`, `rate`,
+/// `difficulty`, optionally `size`, and `length` lines, then one line per
+/// frame the player did something, as
+/// the frame number followed by its commands, e.g. `120 left shoot`. Frame
+/// numbers count every step since the game started, so they don't restart
+/// between waves like `FrameState::frame` does.
+#[derive(Clone)]
+pub struct Replay {
+    seed: u64,
+    /// Name of the level the game started on.
+    level: String,
+    /// Ticks a second it was played at. The timings depend on it.
+    rate: TickRate,
+    rules: Rules,
+    events: Vec<(u32, Vec<CtrlEvent>)>,
+    length: u32,
+}
+
+impl fmt::Display for CtrlEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CtrlEvent::Left => write!(f, "left"),
+            CtrlEvent::Right => write!(f, "right"),
+            CtrlEvent::Shoot => write!(f, "shoot"),
+            CtrlEvent::Pause => write!(f, "pause"),
+            CtrlEvent::Quit => write!(f, "quit"),
+        }
+    }
+}
+
+impl FromStr for CtrlEvent {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "left" => Ok(CtrlEvent::Left),
+            "right" => Ok(CtrlEvent::Right),
+            "shoot" => Ok(CtrlEvent::Shoot),
+            "pause" => Ok(CtrlEvent::Pause),
+            "quit" => Ok(CtrlEvent::Quit),
+            s => Err(format_err!("Unknown command: {:?}", s)),
+        }
+    }
+}
+
+/// The file's contents.
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "level {}", self.level)?;
+        writeln!(f, "rate {}", self.rate.per_second())?;
+        writeln!(f, "difficulty {}", self.rules.difficulty)?;
+        if let Some(size) = self.rules.size {
+            writeln!(f, "size {}x{}", size.0, size.1)?;
+        }
+        writeln!(f, "length {}", self.length)?;
+
+        for (frame, events) in &self.events {
+            write!(f, "{}", frame)?;
+            for event in events {
+                write!(f, " {}", event)?;
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Replay {
+    pub fn new(seed: u64, level: &Level, rate: TickRate) -> Self {
+        Self {
+            seed,
+            level: level.name.clone(),
+            rate,
+            rules: level.rules,
+            events: Vec::new(),
+            length: 0,
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn level(&self) -> &str {
+        &self.level
+    }
+
+    pub fn rate(&self) -> TickRate {
+        self.rate
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    /// How many frames were played.
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+
+    /// Adds the commands for the next frame.
+    pub fn record(&mut self, frame: u32, events: &[CtrlEvent]) {
+        if !events.is_empty() {
+            self.events.push((frame, events.to_vec()));
+        }
+        self.length = frame + 1;
+    }
+
+    /// The commands given on a frame, if any.
+    pub fn events_at(&self, frame: u32) -> &[CtrlEvent] {
+        match self.events.binary_search_by_key(&frame, |(at, _)| *at) {
+            Ok(index) => &self.events[index].1,
+            Err(_) => &[],
+        }
+    }
+
+    /// Sets up freshly loaded levels the way they were recorded, starting
+    /// on the same one and with the same rules.
+    pub fn arrange_levels(&self, levels: Vec<Level>) -> Result<Vec<Level>, Error> {
+        Level::starting_at(levels, &self.level)
+            .map_err(|e| format_err!("Replay starts on an unknown level: {}", e))?
+            .into_iter()
+            .map(|level| level.with_rules(self.rules))
+            .collect()
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(source: &str) -> Result<Self, Error> {
+        let mut lines = source.lines().enumerate();
+
+        match lines.next() {
+            Some((_, header)) if header.trim() == HEADER => (),
+            // The same commands would play out differently in this version
+            Some((_, header)) if header.starts_with(HEADER_PREFIX) => {
+                let version = header.trim().trim_start_matches(HEADER_PREFIX);
+                return Err(format_err!(
+                    "Recorded by another version of the game (v{}), it wouldn't play back the same",
+                    version
+                ));
+            }
+            Some((_, header)) => return Err(format_err!("Unrecognised header: {:?}", header)),
+            None => return Err(format_err!("File is empty")),
+        }
+
+        let mut seed = None;
+        let mut level = None;
+        let mut rate = TickRate::default();
+        let mut rules = Rules::default();
+        let mut length = None;
+        let mut events: Vec<(u32, Vec<CtrlEvent>)> = Vec::new();
+
+        for (number, line) in lines {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (first, rest) = match line.find(char::is_whitespace) {
+                Some(split) => (&line[..split], line[split..].trim()),
+                None => (line, ""),
+            };
+            let bad = |e: &dyn fmt::Display| format_err!("Line {}: {}", number + 1, e);
+
+            match first {
+                "seed" => seed = Some(rest.parse::<u64>().map_err(|e| bad(&e))?),
+                "level" => level = Some(rest.to_string()),
+                "rate" => {
+                    let per_second = rest.parse().map_err(|e| bad(&e))?;
+                    rate = TickRate::new(per_second).map_err(|e| bad(&e))?;
+                }
+                "difficulty" => rules.difficulty = rest.parse().map_err(|e| bad(&e))?,
+                "size" => rules.size = Some(level::parse_size(rest).map_err(|e| bad(&e))?),
+                "length" => length = Some(rest.parse::<u32>().map_err(|e| bad(&e))?),
+                frame => {
+                    let frame: u32 = frame.parse().map_err(|e| bad(&e))?;
+
+                    if events.last().is_some_and(|(at, _)| *at >= frame) {
+                        return Err(bad(&format!("frame {} is out of order", frame)));
+                    }
+
+                    let commands = rest
+                        .split_whitespace()
+                        .map(|event| event.parse().map_err(|e| bad(&e)))
+                        .collect::<Result<Vec<CtrlEvent>, Error>>()?;
+
+                    events.push((frame, commands));
+                }
+            }
+        }
+
+        let seed = seed.ok_or_else(|| format_err!("Missing seed"))?;
+        let level = level.ok_or_else(|| format_err!("Missing level"))?;
+        let last = events.last().map_or(0, |(at, _)| at + 1);
+        let length = length.unwrap_or(last);
+
+        if length < last {
+            return Err(format_err!("Length {} is shorter than the last frame", length));
+        }
+
+        Ok(Self {
+            seed,
+            level,
+            rate,
+            rules,
+            events,
+            length,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recorded() -> Replay {
+        let level = &Level::defaults()[1];
+        let mut replay = Replay::new(u64::MAX, level, TickRate::default());
+        replay.record(0, &[]);
+        replay.record(1, &[CtrlEvent::Left, CtrlEvent::Shoot]);
+        replay.record(2, &[]);
+        replay.record(3, &[CtrlEvent::Right]);
+        replay.record(4, &[]);
+        replay
+    }
+
+    #[test]
+    fn round_trips_through_the_file_format() {
+        let replay = recorded();
+        let text = replay.to_string();
+        let parsed = Replay::parse(&text).unwrap();
+
+        assert_eq!(parsed.to_string(), text);
+        assert_eq!(parsed.seed(), u64::MAX);
+        assert_eq!(parsed.level(), "Second Wave");
+        assert_eq!(parsed.length(), 5);
+        assert_eq!(parsed.events_at(1), &[CtrlEvent::Left, CtrlEvent::Shoot]);
+        assert_eq!(parsed.events_at(2), &[]);
+        assert_eq!(parsed.events_at(3), &[CtrlEvent::Right]);
+    }
+
+    #[test]
+    fn rejects_frames_out_of_order() {
+        let text = format!("{}\nseed 1\nlevel Swarm\n5 left\n3 right\n", HEADER);
+        let error = Replay::parse(&text).err().unwrap().to_string();
+
+        assert_eq!(error, "Line 5: frame 3 is out of order");
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        for version in &["v2", "v4"] {
+            let text = recorded().to_string().replacen("v3", version, 1);
+            let error = Replay::parse(&text).err().unwrap().to_string();
+
+            assert!(error.contains(&format!("another version of the game ({})", version)), "{}", error);
+        }
+    }
+}
@@ -0,0 +1,320 @@
+use crate::entities::{Bunker, Invader, Missile, Player, Ufo};
+use crate::game::game_loop::{FireSchedule, UfoEvent};
+use crate::game::swarm::Swarm;
+use crate::level::{Level, Rules};
+use crate::score::Score;
+use crate::utils::{Coord, Dir};
+use failure::Error;
+use rand_pcg::Pcg32;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Bump this whenever `SavedGame` changes, so older saves are turned away
+/// with a message instead of being misread.
+pub const VERSION: u32 = 2;
+
+/// A game in progress, written to disk so it can be picked up later.
+///
+/// This is everything in a `GameLoop` that can't be worked out again from
+/// the levels and the tick rate. The levels themselves are kept by name,
+/// and found again when the game is loaded. Saves are TOML, with the
+/// numbers too big for TOML, the seed and the RNG state, kept as strings.
+#[derive(Serialize, Deserialize)]
+pub struct SavedGame {
+    pub version: u32,
+    #[serde(with = "big_number")]
+    pub seed: u64,
+    #[serde(with = "rng_state")]
+    pub rng: Pcg32,
+    /// Ticks a second, which all the timers count in.
+    pub rate: u32,
+    /// Names of the levels, in the order they're played.
+    pub levels: Vec<String>,
+    pub rules: Rules,
+    pub wave: usize,
+    pub frame: u32,
+    pub tick: u32,
+    pub interstitial: u32,
+    pub score: Score,
+    pub spawn: Coord,
+    pub player: Player,
+    pub invaders: Vec<Invader>,
+    pub swarm: Swarm,
+    pub missiles: Vec<Missile>,
+    pub bunkers: Vec<Bunker>,
+    pub ufo: Option<Ufo>,
+    pub ufo_timer: u32,
+    pub ufo_event: Option<UfoEvent>,
+    pub ufo_event_timer: u32,
+    pub fire_schedule: FireSchedule,
+    pub player_hit: bool,
+    /// The game's recording so far, in the replay file format, so it can
+    /// still be saved whole once the game is over.
+    pub recording: String,
+}
+
+impl SavedGame {
+    /// Where the game is saved, under the XDG data directory. There's only
+    /// the one slot.
+    pub fn path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("space-invaders").join("save.toml"))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(source: &str) -> Result<Self, Error> {
+        // Check the version before anything else, an old save won't match the rest
+        let value: toml::Value = toml::from_str(source)?;
+
+        match value.get("version").and_then(|version| version.as_integer()) {
+            Some(version) if version == VERSION as i64 => (),
+            Some(version) if version > VERSION as i64 => {
+                return Err(format_err!("Saved by a newer version of the game (v{})", version))
+            }
+            Some(version) => {
+                return Err(format_err!(
+                    "Saved by an older version of the game (v{}), it can't be loaded",
+                    version
+                ))
+            }
+            None => return Err(format_err!("Not a saved game")),
+        }
+
+        Ok(value.try_into()?)
+    }
+
+    /// The file's contents.
+    pub fn to_toml(&self) -> Result<String, Error> {
+        // Going through a `Value` puts the tables after the plain values, as TOML needs
+        Ok(toml::Value::try_from(self)?.to_string())
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let path = Self::path().ok_or_else(|| format_err!("No data directory available"))?;
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        fs::write(&path, self.to_toml()?)?;
+        Ok(())
+    }
+
+    /// Makes sure everything is somewhere the game can cope with, on a
+    /// field of the given size.
+    pub fn check(&self, size: Coord) -> Result<(), Error> {
+        let on_map = |pos: &Coord| pos.0 < size.0 && pos.1 < size.1;
+        let off_map = |what: &str, pos: &Coord| format_err!("The save has {} off the map, at ({}, {})", what, pos.0, pos.1);
+
+        for pos in &[self.spawn, self.player.position] {
+            let (left, right) = Player::SPRITE.reach();
+            if pos.0 < left || pos.0 + right >= size.0 || pos.1 < Player::SPRITE.origin.1 || pos.1 >= size.1 {
+                return Err(off_map("the player", pos));
+            }
+        }
+
+        if let Some(invader) = self.invaders.iter().find(|invader| !on_map(&invader.position)) {
+            return Err(off_map("an invader", &invader.position));
+        }
+
+        if let Some(bunker) = self.bunkers.iter().find(|bunker| !on_map(&bunker.position)) {
+            return Err(off_map("a bunker", &bunker.position));
+        }
+
+        for missile in &self.missiles {
+            if !on_map(&missile.position) {
+                return Err(off_map("a missile", &missile.position));
+            }
+            if missile.direction != Dir::Up && missile.direction != Dir::Down {
+                return Err(format_err!("The save has a missile going sideways"));
+            }
+        }
+
+        if let Some(ufo) = &self.ufo {
+            if !on_map(&ufo.position) || ufo.position.0 + ufo.width() > size.0 {
+                return Err(off_map("the UFO", &ufo.position));
+            }
+            if ufo.direction != Dir::Left && ufo.direction != Dir::Right {
+                return Err(format_err!("The save has a UFO going up or down"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finds the saved game's levels among the ones available, and sets
+    /// them up the same way. Levels already set up that way are used as
+    /// they are.
+    pub fn arrange_levels(&self, available: &[Level]) -> Result<Vec<Level>, Error> {
+        self.levels
+            .iter()
+            .map(|name| {
+                let level = available
+                    .iter()
+                    .find(|level| {
+                        level.name == *name && (level.rules == self.rules || level.rules == Rules::default())
+                    })
+                    .ok_or_else(|| format_err!("The save needs level {:?}, which isn't available", name))?;
+
+                if level.rules == self.rules {
+                    Ok(level.clone())
+                } else {
+                    level.clone().with_rules(self.rules)
+                }
+            })
+            .collect()
+    }
+}
+
+/// A `u64` as a string, as TOML only has signed integers.
+mod big_number {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(n: &u64, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&n.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<u64, D::Error> {
+        String::deserialize(d)?.parse().map_err(de::Error::custom)
+    }
+}
+
+/// The RNG only shows its state through serde, as numbers too big for
+/// TOML. This writes it out with its own field names, but as strings.
+mod rng_state {
+    use rand_pcg::Pcg32;
+    use serde::de::value::{self, MapDeserializer};
+    use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::BTreeMap;
+
+    pub fn serialize<S: Serializer>(rng: &Pcg32, s: S) -> Result<S::Ok, S::Error> {
+        // TOML can write the numbers, it just can't read them back
+        let text = toml::to_string(rng).map_err(ser::Error::custom)?;
+        let fields = text
+            .lines()
+            .filter_map(|line| {
+                let mut parts = line.splitn(2, " = ");
+                Some((parts.next()?.to_string(), parts.next()?.to_string()))
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        fields.serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Pcg32, D::Error> {
+        let fields = BTreeMap::<String, String>::deserialize(d)?
+            .into_iter()
+            .map(|(name, n)| n.parse::<u64>().map(|n| (name, n)))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(de::Error::custom)?;
+
+        Pcg32::deserialize(MapDeserializer::<_, value::Error>::new(fields.into_iter()))
+            .map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::game_loop::{CtrlEvent, GameLoop};
+    use crate::game::GameAction;
+
+    fn commands(tick: usize) -> Vec<CtrlEvent> {
+        match tick % 5 {
+            0 => vec![CtrlEvent::Shoot],
+            1 => vec![CtrlEvent::Left],
+            3 => vec![CtrlEvent::Right],
+            _ => Vec::new(),
+        }
+    }
+
+    /// A game some way in, saved to text.
+    fn saved() -> (GameLoop, String) {
+        let mut game = GameLoop::new(Level::defaults(), 3).unwrap();
+        for tick in 0..400 {
+            game.step(commands(tick));
+        }
+
+        let text = game.to_save().to_toml().unwrap();
+        (game, text)
+    }
+
+    #[test]
+    fn resumes_exactly_where_it_left_off() {
+        let (mut game, text) = saved();
+        let saved = SavedGame::parse(&text).unwrap();
+        let mut resumed = GameLoop::from_save(*game.screen(), &Level::defaults(), saved).unwrap();
+
+        assert_eq!(resumed.to_save().to_toml().unwrap(), text);
+
+        for tick in 400..1200 {
+            let one = game.step(commands(tick));
+            let two = resumed.step(commands(tick));
+
+            assert_eq!(game.to_save().to_toml().unwrap(), resumed.to_save().to_toml().unwrap());
+            if matches!((one, two), (GameAction::GameOver, _) | (_, GameAction::GameOver)) {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn refuses_older_saves() {
+        let (_, text) = saved();
+        let text = text.replacen(&format!("version = {}", VERSION), "version = 1", 1);
+        let error = SavedGame::parse(&text).err().unwrap().to_string();
+
+        assert_eq!(error, "Saved by an older version of the game (v1), it can't be loaded");
+    }
+
+    #[test]
+    fn refuses_newer_saves() {
+        let (_, text) = saved();
+        let text = text.replacen(&format!("version = {}", VERSION), "version = 99", 1);
+        let error = SavedGame::parse(&text).err().unwrap().to_string();
+
+        assert_eq!(error, "Saved by a newer version of the game (v99)");
+    }
+
+    /// Loads the saved game after making a change to the file.
+    fn load_changed(change: impl FnOnce(&mut toml::Value)) -> Result<GameLoop, Error> {
+        let (game, text) = saved();
+        let mut value: toml::Value = toml::from_str(&text).unwrap();
+        change(&mut value);
+
+        let saved = SavedGame::parse(&value.to_string())?;
+        GameLoop::from_save(*game.screen(), &Level::defaults(), saved)
+    }
+
+    fn refusal(change: impl FnOnce(&mut toml::Value)) -> String {
+        match load_changed(change) {
+            Ok(_) => panic!("The save should have been refused"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn refuses_an_empty_swarm() {
+        let error = refusal(|save| save["swarm"]["size"] = toml::Value::Integer(0));
+        assert!(error.contains("size must be at least 1"), "{}", error);
+    }
+
+    #[test]
+    fn refuses_sideways_missiles() {
+        let missile = toml::Value::try_from(Missile::new(Coord(0, 5), Dir::Left)).unwrap();
+        let error = refusal(|save| save["missiles"] = toml::Value::Array(vec![missile]));
+        assert_eq!(error, "The save has a missile going sideways");
+    }
+
+    #[test]
+    fn refuses_things_off_the_map() {
+        let error = refusal(|save| save["invaders"][0]["position"][0] = toml::Value::Integer(45));
+        assert!(error.starts_with("The save has an invader off the map"), "{}", error);
+
+        let error = refusal(|save| save["player"]["position"][0] = toml::Value::Integer(0));
+        assert!(error.starts_with("The save has the player off the map"), "{}", error);
+    }
+}
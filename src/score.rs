@@ -0,0 +1,42 @@
+use crate::entities::InvaderKind;
+use serde::{Deserialize, Serialize};
+
+/// Every this many consecutive hits bumps the multiplier by one.
+const STREAK_STEP: u32 = 5;
+const MAX_MULTIPLIER: u32 = 4;
+
+/// Running score for a game. Consecutive hits build a streak that
+/// multiplies the points of each kill. Missing a shot resets it.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct Score {
+    total: u32,
+    streak: u32,
+}
+
+impl Score {
+    pub fn total(&self) -> u32 {
+        self.total
+    }
+
+    pub fn multiplier(&self) -> u32 {
+        std::cmp::min(1 + self.streak / STREAK_STEP, MAX_MULTIPLIER)
+    }
+
+    /// Records a kill and returns the points it was worth.
+    pub fn kill(&mut self, kind: InvaderKind) -> u32 {
+        let points = kind.points() * self.multiplier();
+        self.total = self.total.saturating_add(points);
+        self.streak += 1;
+        points
+    }
+
+    /// Flat bonus points, which still count towards the streak.
+    pub fn bonus(&mut self, points: u32) {
+        self.total = self.total.saturating_add(points);
+        self.streak += 1;
+    }
+
+    pub fn miss(&mut self) {
+        self.streak = 0;
+    }
+}
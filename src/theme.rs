@@ -0,0 +1,462 @@
+//! Colours for everything drawn, and how many colours the terminal can
+//! show. Themes name a style for each kind of thing on screen, and the
+//! renderer turns those into escape codes the terminal understands.
+
+use crate::utils::{self, Tile};
+use failure::Error;
+use serde::{de, Deserialize, Deserializer};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The themes shipped with the game. The first is used unless another is picked.
+const DEFAULT_THEMES: &[&str] = &[
+    include_str!("../themes/classic.toml"),
+    include_str!("../themes/high-contrast.toml"),
+    include_str!("../themes/colour-blind.toml"),
+    include_str!("../themes/mono.toml"),
+];
+
+/// The names the 16 standard colours go by in theme files, in ANSI order.
+const NAMES: [&str; 16] = [
+    "black",
+    "red",
+    "green",
+    "yellow",
+    "blue",
+    "magenta",
+    "cyan",
+    "white",
+    "bright-black",
+    "bright-red",
+    "bright-green",
+    "bright-yellow",
+    "bright-blue",
+    "bright-magenta",
+    "bright-cyan",
+    "bright-white",
+];
+
+/// What the 16 standard colours usually look like (xterm's defaults), to
+/// find the closest one to any other colour.
+const PALETTE: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// How many colours to draw with.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ColourMode {
+    /// Plain text, no escape codes at all.
+    Off,
+    /// The 16 standard colours, which every colour terminal has.
+    Basic,
+    /// The 256 colour palette.
+    Indexed,
+    /// Any colour, as 24-bit RGB.
+    TrueColour,
+}
+
+/// A colour in a theme: one of the 16 standard ones, which look however
+/// the terminal's palette says, or an exact one.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Colour {
+    Named(u8),
+    Rgb(u8, u8, u8),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Style {
+    pub fg: Option<Colour>,
+    pub bg: Option<Colour>,
+    pub bold: bool,
+}
+
+/// The kinds of things drawn, each of which a theme can give its own style.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Element {
+    Player,
+    Invader,
+    Missile,
+    Bunker,
+    Ufo,
+    Explosion,
+    Border,
+    /// Menus and messages, and anything else without a style of its own.
+    Text,
+    /// The menu item under the cursor.
+    Selected,
+    Score,
+    Lives,
+    Wave,
+    /// The HUD line under the score.
+    Message,
+}
+
+/// Anything left out of a theme file is drawn in the terminal's own colours.
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    pub name: String,
+    pub player: Style,
+    pub invader: Style,
+    pub missile: Style,
+    pub bunker: Style,
+    pub ufo: Style,
+    pub explosion: Style,
+    pub border: Style,
+    pub text: Style,
+    pub selected: Style,
+    pub score: Style,
+    pub lives: Style,
+    pub wave: Style,
+    pub message: Style,
+}
+
+impl fmt::Display for ColourMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ColourMode::Off => write!(f, "none"),
+            ColourMode::Basic => write!(f, "16"),
+            ColourMode::Indexed => write!(f, "256"),
+            ColourMode::TrueColour => write!(f, "truecolor"),
+        }
+    }
+}
+
+impl FromStr for ColourMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "auto" => Ok(Self::detect()),
+            "none" => Ok(ColourMode::Off),
+            "16" => Ok(ColourMode::Basic),
+            "256" => Ok(ColourMode::Indexed),
+            "truecolor" | "truecolour" | "24bit" => Ok(ColourMode::TrueColour),
+            s => Err(format_err!("Unknown colour mode {:?}, try auto, none, 16, 256 or truecolor", s)),
+        }
+    }
+}
+
+impl ColourMode {
+    /// Works out what the terminal can show from the environment, the same
+    /// way most terminal programs do. `NO_COLOR` turns colour off.
+    pub fn detect() -> Self {
+        let var = |name| env::var(name).unwrap_or_default();
+
+        if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+            return ColourMode::Off;
+        }
+
+        let term = var("TERM");
+        let colorterm = var("COLORTERM");
+
+        if term.is_empty() || term == "dumb" {
+            ColourMode::Off
+        } else if colorterm == "truecolor" || colorterm == "24bit" {
+            ColourMode::TrueColour
+        } else if term.contains("256color") {
+            ColourMode::Indexed
+        } else {
+            ColourMode::Basic
+        }
+    }
+}
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+/// The closest of the 16 standard colours.
+fn nearest_basic(rgb: (u8, u8, u8)) -> u8 {
+    (0..16).min_by_key(|&i| distance(rgb, PALETTE[i as usize])).unwrap_or(7)
+}
+
+/// The closest colour in the 256 colour palette's colour cube or grey ramp.
+/// The first 16 are left out, as terminals change what those look like.
+fn nearest_indexed(rgb: (u8, u8, u8)) -> u8 {
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    let level = |c: u8| (0..6).min_by_key(|&i| (LEVELS[i] as i32 - c as i32).abs()).unwrap_or(0);
+
+    let (r, g, b) = (level(rgb.0), level(rgb.1), level(rgb.2));
+    let cube = (LEVELS[r], LEVELS[g], LEVELS[b]);
+
+    let average = (rgb.0 as u32 + rgb.1 as u32 + rgb.2 as u32) / 3;
+    let step = (average.saturating_sub(3) / 10).min(23) as u8;
+    let grey = 8 + 10 * step;
+
+    if distance(rgb, (grey, grey, grey)) < distance(rgb, cube) {
+        232 + step
+    } else {
+        16 + 36 * r as u8 + 6 * g as u8 + b as u8
+    }
+}
+
+impl FromStr for Colour {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        if let Some(i) = NAMES.iter().position(|name| *name == s) {
+            return Ok(Colour::Named(i as u8));
+        }
+
+        let bad = || format_err!("Colours should be a name like \"bright-red\" or look like \"#ff8800\", not {:?}", s);
+
+        match s.strip_prefix('#') {
+            Some(hex) if hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+                let part = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| bad());
+                Ok(Colour::Rgb(part(0)?, part(2)?, part(4)?))
+            }
+            _ => Err(bad()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Colour {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        String::deserialize(d)?.parse().map_err(de::Error::custom)
+    }
+}
+
+impl Colour {
+    /// The SGR parameters for this colour, as a foreground if `base` is 30
+    /// or a background if it's 40.
+    fn sgr(self, base: u8, mode: ColourMode) -> String {
+        let basic = |i: u8| if i < 8 { base + i } else { base + 60 + i - 8 };
+
+        match (self, mode) {
+            // Left to the terminal's palette, whatever the mode
+            (Colour::Named(i), _) => basic(i).to_string(),
+            (Colour::Rgb(r, g, b), ColourMode::TrueColour) => format!("{};2;{};{};{}", base + 8, r, g, b),
+            (Colour::Rgb(r, g, b), ColourMode::Indexed) => format!("{};5;{}", base + 8, nearest_indexed((r, g, b))),
+            (Colour::Rgb(r, g, b), _) => basic(nearest_basic((r, g, b))).to_string(),
+        }
+    }
+}
+
+impl Style {
+    /// The escape code that switches to this style, or nothing if it's the
+    /// terminal's own. Follow it with `RESET` once the text is written.
+    pub fn escape(&self, mode: ColourMode) -> String {
+        if mode == ColourMode::Off || *self == Style::default() {
+            return String::new();
+        }
+
+        let mut params = Vec::new();
+        if self.bold {
+            params.push("1".to_string());
+        }
+        if let Some(fg) = self.fg {
+            params.push(fg.sgr(30, mode));
+        }
+        if let Some(bg) = self.bg {
+            params.push(bg.sgr(40, mode));
+        }
+
+        format!("\x1b[{}m", params.join(";"))
+    }
+}
+
+/// Puts the terminal back to its own colours.
+pub const RESET: &str = "\x1b[0m";
+
+impl From<Tile> for Element {
+    fn from(tile: Tile) -> Self {
+        match tile {
+            Tile::Invader(_) => Element::Invader,
+            Tile::Player => Element::Player,
+            Tile::Missile(_) => Element::Missile,
+            Tile::Bunker(_) => Element::Bunker,
+            Tile::Ufo => Element::Ufo,
+            Tile::Explosion => Element::Explosion,
+            Tile::None => Element::Text,
+        }
+    }
+}
+
+impl Theme {
+    pub fn parse(source: &str) -> Result<Self, Error> {
+        let theme: Theme = toml::from_str(source)?;
+
+        if theme.name.is_empty() {
+            return Err(format_err!("Theme needs a name"));
+        }
+
+        Ok(theme)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn defaults() -> Vec<Self> {
+        DEFAULT_THEMES
+            .iter()
+            .map(|source| Self::parse(source).expect("Built-in theme is invalid"))
+            .collect()
+    }
+
+    /// Where custom themes are looked for, under the XDG config directory.
+    pub fn dir() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("space-invaders").join("themes"))
+    }
+
+    /// The built-in themes, then any from the config directory. A custom
+    /// theme with the same name as a built-in one takes its place.
+    pub fn available() -> Vec<Self> {
+        let mut rv = Self::defaults();
+
+        let custom = match Self::dir() {
+            Some(dir) if dir.is_dir() => utils::load_dir(&dir, Self::load).unwrap_or_else(|e| {
+                warn!("Skipping custom themes, failed to load them: {}", e);
+                Vec::new()
+            }),
+            _ => Vec::new(),
+        };
+
+        for theme in custom {
+            match rv.iter_mut().find(|other| other.name == theme.name) {
+                Some(other) => *other = theme,
+                None => rv.push(theme),
+            }
+        }
+
+        rv
+    }
+
+    /// The theme called `name`, out of those available.
+    pub fn named(name: &str) -> Result<Self, Error> {
+        let themes = Self::available();
+
+        match themes.iter().find(|theme| theme.name == name) {
+            Some(theme) => Ok(theme.clone()),
+            None => {
+                let names = themes.iter().map(|theme| theme.name.as_str()).collect::<Vec<_>>();
+                Err(format_err!("No theme called {:?}, there's {}", name, names.join(", ")))
+            }
+        }
+    }
+
+    pub fn style(&self, element: Element) -> Style {
+        match element {
+            Element::Player => self.player,
+            Element::Invader => self.invader,
+            Element::Missile => self.missile,
+            Element::Bunker => self.bunker,
+            Element::Ufo => self.ufo,
+            Element::Explosion => self.explosion,
+            Element::Border => self.border,
+            Element::Text => self.text,
+            Element::Selected => self.selected,
+            Element::Score => self.score,
+            Element::Lives => self.lives,
+            Element::Wave => self.wave,
+            Element::Message => self.message,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn style(fg: Option<Colour>, bg: Option<Colour>, bold: bool) -> Style {
+        Style { fg, bg, bold }
+    }
+
+    #[test]
+    fn parses_colour_names_and_hex() {
+        assert_eq!("red".parse::<Colour>().unwrap(), Colour::Named(1));
+        assert_eq!("bright-white".parse::<Colour>().unwrap(), Colour::Named(15));
+        assert_eq!("#ff8800".parse::<Colour>().unwrap(), Colour::Rgb(255, 136, 0));
+        assert_eq!("#0A0b0C".parse::<Colour>().unwrap(), Colour::Rgb(10, 11, 12));
+    }
+
+    #[test]
+    fn rejects_bad_colours() {
+        for bad in &["", "pink", "ff8800", "#ff88", "#ff88001", "#gg0000", "#ffé00", "#+f+f+f"] {
+            assert!(bad.parse::<Colour>().is_err(), "{:?} should be refused", bad);
+        }
+    }
+
+    #[test]
+    fn parses_colour_modes() {
+        assert_eq!("none".parse::<ColourMode>().unwrap(), ColourMode::Off);
+        assert_eq!("16".parse::<ColourMode>().unwrap(), ColourMode::Basic);
+        assert_eq!("256".parse::<ColourMode>().unwrap(), ColourMode::Indexed);
+        assert_eq!("truecolor".parse::<ColourMode>().unwrap(), ColourMode::TrueColour);
+        assert_eq!("24bit".parse::<ColourMode>().unwrap(), ColourMode::TrueColour);
+        assert!("88".parse::<ColourMode>().is_err());
+    }
+
+    #[test]
+    fn finds_the_nearest_basic_colour() {
+        assert_eq!(nearest_basic((0, 0, 0)), 0);
+        assert_eq!(nearest_basic((250, 10, 10)), 9);
+        assert_eq!(nearest_basic((200, 190, 10)), 3);
+        assert_eq!(nearest_basic((130, 120, 125)), 8);
+    }
+
+    #[test]
+    fn picks_greys_from_the_ramp_and_colours_from_the_cube() {
+        assert_eq!(nearest_indexed((128, 128, 128)), 244);
+        assert_eq!(nearest_indexed((8, 8, 8)), 232);
+        assert_eq!(nearest_indexed((255, 0, 0)), 196);
+        assert_eq!(nearest_indexed((95, 135, 175)), 67);
+        assert_eq!(nearest_indexed((255, 255, 255)), 231);
+    }
+
+    #[test]
+    fn writes_no_escapes_without_colour() {
+        let bold_red = style(Some(Colour::Named(1)), Some(Colour::Rgb(0, 0, 0)), true);
+
+        assert_eq!(bold_red.escape(ColourMode::Off), "");
+        assert_eq!(Style::default().escape(ColourMode::TrueColour), "");
+    }
+
+    #[test]
+    fn writes_named_colours_the_same_in_every_mode() {
+        let bright = style(Some(Colour::Named(9)), Some(Colour::Named(12)), true);
+        let dark = style(Some(Colour::Named(2)), Some(Colour::Named(0)), false);
+
+        for mode in &[ColourMode::Basic, ColourMode::Indexed, ColourMode::TrueColour] {
+            assert_eq!(bright.escape(*mode), "\x1b[1;91;104m");
+            assert_eq!(dark.escape(*mode), "\x1b[32;40m");
+        }
+    }
+
+    #[test]
+    fn writes_exact_colours_as_well_as_the_mode_allows() {
+        let orange = style(Some(Colour::Rgb(255, 135, 0)), None, false);
+
+        assert_eq!(orange.escape(ColourMode::TrueColour), "\x1b[38;2;255;135;0m");
+        assert_eq!(orange.escape(ColourMode::Indexed), "\x1b[38;5;208m");
+        assert_eq!(orange.escape(ColourMode::Basic), "\x1b[33m");
+    }
+
+    #[test]
+    fn ships_valid_themes() {
+        let names = Theme::defaults().into_iter().map(|theme| theme.name).collect::<Vec<_>>();
+        assert_eq!(names.len(), DEFAULT_THEMES.len());
+        assert!(names.iter().all(|name| !name.is_empty()));
+    }
+}
@@ -1,4 +1,9 @@
-#[derive(Debug, PartialEq, Clone, Copy)]
+use failure::Error;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum Dir {
     Up,
     Down,
@@ -11,38 +16,30 @@ pub enum Tile {
     Invader(usize),
     Player,
     Missile(usize),
+    Bunker(usize),
+    Ufo,
     Explosion,
     None,
 }
 
+/// The area the loops draw in. Where it ends up in the terminal is up to
+/// the renderer, which keeps it centred as the terminal changes size.
 #[derive(Clone, Copy)]
 pub struct Screen {
-    margins: Coord,
     size: Coord,
 }
 
 impl Screen {
-    pub fn new(margins: Coord, size: Coord) -> Self {
-        Self {
-            margins,
-            size
-        }
-    }
-
-    pub fn margins(&self) -> &Coord {
-        &self.margins
+    pub fn new(size: Coord) -> Self {
+        Self { size }
     }
 
     pub fn size(&self) -> &Coord {
         &self.size
     }
-
-	pub fn frame_buffer_size(&self) -> usize {
-		(self.margins.0 + self.size.0) * (self.margins.1 + self.size.1)
-	}
 }
 
-#[derive(Copy, Clone)]
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
 pub struct Coord (pub usize, pub usize);
 
 impl Coord {
@@ -51,10 +48,17 @@ impl Coord {
     }
 }
 
-pub fn looped_inc<T: num::Integer + num::Unsigned + num::Bounded + Copy>(num: &mut T) {
-    *num = (*num + T::one()) % (T::max_value() - T::one());
-}
+/// Loads every `.toml` file in `dir` with `load`, in file name order.
+/// Errors say which file they came from.
+pub fn load_dir<T>(dir: &Path, load: impl Fn(&Path) -> Result<T, Error>) -> Result<Vec<T>, Error> {
+    let mut paths = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .collect::<Vec<_>>();
+    paths.sort();
 
-pub fn capped_inc<T: num::Integer + num::Unsigned + num::Bounded + Copy>(num: &mut T) {
-    *num = std::cmp::min(*num + T::one(), T::max_value() - T::one());
+    paths
+        .iter()
+        .map(|path| load(path).map_err(|e| format_err!("{}: {}", path.display(), e)))
+        .collect()
 }